@@ -1,9 +1,12 @@
 use sfml::graphics::*;
 use sfml::system::Vector2f;
 use sfml::window::*;
-use sfml::window::mouse::Button;
 
 use crate::AssetManager;
+use crate::animation::{AnimationSet, Animator};
+use crate::sim::*;
+use crate::theme::Theme;
+use crate::tween::{Easing, Tween};
 
 use super::{WIN_HEIGHT, WIN_WIDTH};
 
@@ -15,53 +18,37 @@ pub trait Actor {
     fn events(&mut self, evt: Event);
 }
 
-struct Circle {
-    x: f32,
-    y: f32,
-    radius: f32,
-}
-
-impl Circle {
-    fn new(x: f32, y: f32, radius: f32) -> Circle {
-        Circle { x, y, radius }
-    }
-
-    fn is_colliding(&self, other: &Self) -> bool {
-        let dis = {
-            let x = self.x - other.x;
-            let y = self.y - other.y;
-
-            f32::sqrt(x * x + y * y)
-        };
-
-        dis < (self.radius + other.radius)
-    }
-}
-
-const TOWER_INNER: f32 = 100.0;
-const TOWER_OUTER: f32 = 250.0;
+const RING_PULSE_SCALE: f32 = 0.85;
+const RING_PULSE_DURATION: f32 = 0.6;
 
 pub struct Tower<'a> {
+    am: &'a AssetManager,
+    animations: &'a AnimationSet,
+    bullet_animations: &'a AnimationSet,
     sprite: Sprite<'a>,
+    animator: Animator,
+    afterimage: Option<(Sprite<'a>, Animator)>, //where the tower was before its last teleport
     teleport_circle: CircleShape<'a>,
-    hitbox: Circle,
-    position: Vector2f,
-    rotation: f32,
+    ring_pulse: Tween,
     bullet_sprite: Sprite<'a>,
-    bullets: Vec<Bullet<'a>>,
-    pub dead: bool,
+    bullet_animator: Animator,
+    tint: Color,
+    state: TowerState,
 }
 
 impl<'a> Tower<'a> {
-    pub fn new(am: &'a AssetManager) -> Tower<'a> {
-        let position = Vector2f::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0);
+    pub fn new(am: &'a AssetManager, theme: &Theme) -> Tower<'a> {
+        Tower::with_position(am, theme, Vector2f::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0), 0.0)
+    }
 
+    pub fn with_position(am: &'a AssetManager, theme: &Theme, position: Vector2f, rotation: f32) -> Tower<'a> {
+        let animations = am.get_animations("tower.anim");
+        let animator = Animator::new("idle");
         let sprite = {
             let mut s = Sprite::new();
-            let t = am.get_texture("tower.png");
-            s.set_texture(t, true);
+            animator.apply(animations, am, &mut s, Color::WHITE);
             s.set_position(position);
-            s.set_origin(Vector2f { x: t.size().x as f32 / 2.0, y: t.size().y as f32 / 2.0 });
+            s.set_rotation(rotation);
 
             s
         };
@@ -72,149 +59,219 @@ impl<'a> Tower<'a> {
             c.set_outline_thickness(TOWER_OUTER - TOWER_INNER);
             c.set_origin(Vector2f::new(TOWER_INNER, TOWER_INNER));
             c.set_fill_color(&Color::TRANSPARENT);
-            c.set_outline_color(&Color::rgba(255, 255, 255, 50));
+            c.set_outline_color(&theme.ring);
 
             c
         };
 
+        let bullet_animations = am.get_animations("bullet.anim");
+        let bullet_animator = Animator::new("spin");
         let bullet_sprite = {
             let mut s = Sprite::new();
-            let t = am.get_texture("bullet.png");
-            s.set_texture(t, true);
-            s.set_position(position);
-            s.set_origin(Vector2f { x: t.size().x as f32 / 2.0, y: t.size().y as f32 / 2.0 });
+            bullet_animator.apply(bullet_animations, am, &mut s, Color::WHITE);
 
             s
         };
 
-        let hitbox = {
-            Circle::new(position.x, position.y, sprite.texture_rect().width as f32 / 2.0)
-        };
+        let state = TowerState::with_radii(
+            position,
+            rotation,
+            sprite.texture_rect().width as f32 / 2.0,
+            bullet_sprite.texture_rect().width as f32 / 2.0,
+        );
 
         Tower {
+            am,
+            animations,
+            bullet_animations,
             sprite,
+            animator,
+            afterimage: None,
             teleport_circle,
-            hitbox,
-            position,
-            rotation: 0.0,
+            ring_pulse: Tween::fixed(1.0),
             bullet_sprite,
-            bullets: Vec::new(),
-            dead: false,
+            bullet_animator,
+            tint: Color::WHITE,
+            state,
         }
     }
 
-    pub fn num_bullets(&self) -> usize {
-        self.bullets.len()
+    //colors the tower, its ring and its bullets
+    pub fn set_tint(&mut self, color: Color) {
+        self.tint = color;
+        self.animator.apply(self.animations, self.am, &mut self.sprite, color);
+        self.bullet_animator.apply(self.bullet_animations, self.am, &mut self.bullet_sprite, color);
+
+        let ring = Color::rgba(color.r, color.g, color.b, self.teleport_circle.outline_color().a);
+        self.teleport_circle.set_outline_color(&ring);
     }
-}
 
-impl<'a> Actor for Tower<'a> {
-    fn update(&mut self, d: f32) {
-        self.sprite.set_rotation(self.rotation);
-        self.sprite.set_position(self.position);
-        self.teleport_circle.set_position(self.position);
-        self.hitbox.x = self.position.x;
-        self.hitbox.y = self.position.y;
+    pub fn check_hits(&mut self, other: &Tower) {
+        self.state.check_hits(&other.state);
+    }
 
-        for b in &mut self.bullets {
-            b.update(d);
+    pub fn state(&self) -> &TowerState {
+        &self.state
+    }
 
-            if b.hitbox.is_colliding(&self.hitbox) {
-                self.dead = true;
-            }
-        }
+    //for changes that don't come from the player, like bullets spawned by a script
+    pub fn state_mut(&mut self) -> &mut TowerState {
+        &mut self.state
     }
 
-    fn draw(&self, win: &mut RenderWindow) {
-        for b in &self.bullets {
-            b.draw(win);
+    //just the tower itself, without its ring and bullets
+    pub fn draw_tower(&self, win: &mut RenderWindow) {
+        win.draw(&self.sprite);
+    }
+
+    //shows a state that was simulated somewhere else, like on the other end of a network game
+    pub fn show(&mut self, state: &TowerState, d: f32) {
+        if state.teleports() > self.state.teleports() {
+            self.teleported();
         }
 
-        win.draw(&self.sprite);
-        win.draw(&self.teleport_circle);
+        self.state = state.clone();
+        self.sync(d);
     }
 
-    fn events(&mut self, evt: Event) {
-        match evt {
-            Event::MouseButtonPressed { button: Button::Left, x, y } => {
-                self.position += {
-                    let mut m = Vector2f::new(x as f32 - self.position.x, y as f32 - self.position.y);
-                    let mut l = f32::sqrt(m.x.powi(2) + m.y.powi(2));
-
-                    if l != 0.0 {
-                        m.x /= l;
-                        m.y /= l;
-                        l = f32::min(TOWER_OUTER, f32::max(l, TOWER_INNER));
-                        m.x *= l;
-                        m.y *= l;
-
-                        self.bullets.push(Bullet::new(self.bullet_sprite.clone(), self.position, self.rotation));
-                    }
-
-                    m
-                }
-            }
-            Event::MouseMoved { x, y } => {
-                self.rotation = (y as f32 - self.position.y).atan2(x as f32 - self.position.x).to_degrees();
-            }
+    //keeps the animations going while the game itself stands still, like after the tower got hit
+    pub fn animate(&mut self, d: f32) {
+        self.sync(d);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.state.dead
+    }
+
+    pub fn num_bullets(&self) -> usize {
+        self.state.num_bullets()
+    }
+
+    pub fn input(&mut self, input: TowerInput) {
+        let from = (self.state.position, self.state.rotation);
 
-            _ => {}
+        if self.state.input(input) {
+            self.sprite.set_position(from.0);
+            self.sprite.set_rotation(from.1);
+            self.teleported();
         }
     }
-}
 
-const BULLET_SPEED: f32 = 85.0;
+    //leaves a fading copy of the tower behind and lets the tower appear at its new place,
+    //the sprite still has to be where the tower was
+    fn teleported(&mut self) {
+        let mut afterimage = Animator::new("teleport_out");
+        let mut s = self.sprite.clone();
+        afterimage.apply(self.animations, self.am, &mut s, self.tint);
 
-pub struct Bullet<'a> {
-    sprite: Sprite<'a>,
-    hitbox: Circle,
-    position: Vector2f,
-    rotation: f32,
-}
+        self.afterimage = Some((s, afterimage));
+        self.animator.play("teleport_in");
+    }
 
-impl<'a> Bullet<'a> {
-    pub fn new(spr: Sprite<'a>, pos: Vector2f, rot: f32) -> Bullet<'a> {
-        let hitbox = {
-            Circle::new(pos.x, pos.y, spr.texture_rect().width as f32 / 2.0)
+    //all bullets as quads of one vertex array, so thousands of them are still a single draw call
+    fn draw_bullets(&self, win: &mut RenderWindow) {
+        let texture = match self.bullet_sprite.texture() {
+            Some(t) => t,
+            None => return,
         };
 
-        let mut b = Bullet {
-            sprite: spr,
-            hitbox,
-            position: pos,
-            rotation: rot,
-        };
+        if self.state.bullets.is_empty() {
+            return;
+        }
+
+        let rect = self.bullet_sprite.texture_rect();
+        let scale = self.bullet_sprite.get_scale();
+        let half = Vector2f::new(rect.width as f32 / 2.0 * scale.x, rect.height as f32 / 2.0 * scale.y);
+        let color = self.bullet_sprite.color();
+        let turn = self.bullet_animator.frame(self.bullet_animations).map_or(0.0, |f| f.rotation);
 
-        b.update(1.0 / 100.0);
+        let (l, t, r, b) = (rect.left as f32, rect.top as f32, (rect.left + rect.width) as f32, (rect.top + rect.height) as f32);
+        let corners = [(-half.x, -half.y, l, t), (half.x, -half.y, r, t), (half.x, half.y, r, b), (-half.x, half.y, l, b)];
 
-        b
+        let mut vertices = VertexArray::new(PrimitiveType::Quads, 0);
+
+        for bullet in &self.state.bullets {
+            let a = (bullet.rotation + turn).to_radians();
+            let (sin, cos) = a.sin_cos();
+            let p = bullet.position;
+
+            for &(x, y, u, v) in &corners {
+                let position = Vector2f::new(p.x + x * cos - y * sin, p.y + x * sin + y * cos);
+                vertices.append(&Vertex::new(position, color, Vector2f::new(u, v)));
+            }
+        }
+
+        let mut states = RenderStates::default();
+        states.texture = Some(texture);
+        win.draw_with_renderstates(&vertices, states);
     }
-}
 
-impl<'a> Actor for Bullet<'a> {
-    fn update(&mut self, d: f32) {
-        if self.position.x <= 0.0 || self.position.x >= WIN_WIDTH {
-            self.rotation = -self.rotation + 180.0;
+    fn pulse(&mut self) {
+        self.ring_pulse = Tween::new(RING_PULSE_SCALE, 1.0, RING_PULSE_DURATION, Easing::ElasticOut);
+    }
+
+    fn sync(&mut self, d: f32) {
+        if self.state.dead && self.animator.playing() != "death" {
+            self.animator.play("death");
         }
 
-        if self.position.y <= 0.0 || self.position.y >= WIN_HEIGHT {
-            self.rotation = -self.rotation;
+        for e in self.animator.update(self.animations, d) {
+            if e == "pulse" {
+                self.pulse();
+            }
+        }
+
+        if let Some((ref mut s, ref mut a)) = self.afterimage {
+            a.update(self.animations, d);
+            a.apply(self.animations, self.am, s, self.tint);
         }
+        if self.afterimage.as_ref().map_or(false, |(_, a)| a.finished()) {
+            self.afterimage = None;
+        }
+
+        self.bullet_animator.update(self.bullet_animations, d);
+        self.bullet_animator.apply(self.bullet_animations, self.am, &mut self.bullet_sprite, self.tint);
 
-        self.position.x += self.rotation.to_radians().cos() * BULLET_SPEED * d;
-        self.position.y += self.rotation.to_radians().sin() * BULLET_SPEED * d;
+        let turn = self.animator.frame(self.animations).map_or(0.0, |f| f.rotation);
+        self.animator.apply(self.animations, self.am, &mut self.sprite, self.tint);
+        self.sprite.set_rotation(self.state.rotation + turn);
+        self.sprite.set_position(self.state.position);
+        self.teleport_circle.set_position(self.state.position);
+
+        //scripts can change the ring while playing
+        let rules = self.state.rules();
+        if self.teleport_circle.radius() != rules.tower_inner {
+            self.teleport_circle.set_radius(rules.tower_inner);
+            self.teleport_circle.set_origin(Vector2f::new(rules.tower_inner, rules.tower_inner));
+        }
+        self.teleport_circle.set_outline_thickness(rules.tower_outer - rules.tower_inner);
+
+        self.ring_pulse.update(d);
+        let rs = self.ring_pulse.value();
+        self.teleport_circle.set_scale(Vector2f::new(rs, rs));
+    }
+}
 
-        self.sprite.set_rotation(self.rotation);
-        self.sprite.set_position(self.position);
-        self.hitbox.x = self.position.x;
-        self.hitbox.y = self.position.y;
+impl<'a> Actor for Tower<'a> {
+    fn update(&mut self, d: f32) {
+        self.sync(d);
+        self.state.update(d);
     }
 
     fn draw(&self, win: &mut RenderWindow) {
+        self.draw_bullets(win);
+
+        if let Some((ref a, _)) = self.afterimage {
+            win.draw(a);
+        }
+
         win.draw(&self.sprite);
+        win.draw(&self.teleport_circle);
     }
 
-    fn events(&mut self, _evt: Event) {
-        unimplemented!();
+    fn events(&mut self, evt: Event) {
+        if let Some(i) = TowerInput::from_event(evt) {
+            self.input(i);
+        }
     }
 }
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use sfml::graphics::{Color, IntRect, Sprite, Transformable};
+use sfml::system::Vector2f;
+
+use crate::assets::AssetManager;
+use crate::layout::{parse_sections, Section};
+
+// Animations are described in `.anim` files in the same format as the screens, one clip each:
+//
+//   [clip]
+//   name = teleport_in
+//   texture = tower.png
+//   frames = full 0.4 0, full 0.8 180, 0 0 66 66
+//   fps = 30
+//   loop = false
+//   next = idle
+//   events = 0 pulse
+//
+// A frame is a rectangle `x y width height` in the texture, so a sprite sheet holds all frames of
+// a clip side by side, or `full` for the whole texture, which keeps working when a mod replaces
+// the picture with one of another size. Rectangles that reach outside the texture are cut to it.
+// It can be followed by a scale, an alpha and a rotation in degrees, which lets a single picture
+// be animated too. `next` is played when a clip that doesn't loop ends,
+// `events` are fired when the given frame is reached, the actor decides what they mean.
+
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub rect: Option<IntRect>, //None is the whole texture
+    pub scale: f32,
+    pub alpha: u8,
+    pub rotation: f32,
+}
+
+impl Frame {
+    //the part of a texture of this size the frame shows
+    pub fn rect_in(&self, width: i32, height: i32) -> IntRect {
+        let r = match self.rect {
+            Some(r) => r,
+            None => return IntRect::new(0, 0, width, height),
+        };
+
+        let left = r.left.max(0).min(width);
+        let top = r.top.max(0).min(height);
+        let right = (r.left + r.width).max(left).min(width);
+        let bottom = (r.top + r.height).max(top).min(height);
+
+        IntRect::new(left, top, right - left, bottom - top)
+    }
+}
+
+pub struct Clip {
+    pub texture: String,
+    pub frames: Vec<Frame>,
+    pub fps: f32,
+    pub looping: bool,
+    pub next: Option<String>,
+    events: Vec<(usize, String)>,
+}
+
+impl Clip {
+    fn events_at(&self, frame: usize) -> impl Iterator<Item = &String> {
+        self.events.iter().filter(move |(f, _)| *f == frame).map(|(_, e)| e)
+    }
+}
+
+pub struct AnimationSet {
+    clips: HashMap<String, Clip>,
+}
+
+impl AnimationSet {
+    pub fn parse(src: &str) -> Result<AnimationSet, String> {
+        let mut clips = HashMap::new();
+
+        for s in parse_sections(src)? {
+            if s.kind != "clip" {
+                return Err(format!("line {}: unknown section `{}`", s.line, s.kind));
+            }
+
+            let name = s.required("name")?.to_string();
+            clips.insert(name, parse_clip(&s)?);
+        }
+
+        let set = AnimationSet {
+            clips,
+        };
+
+        for (name, c) in &set.clips {
+            if let Some(ref n) = c.next {
+                if !set.clips.contains_key(n) {
+                    return Err(format!("clip `{}` continues with `{}`, which doesn't exist", name, n));
+                }
+            }
+        }
+
+        Ok(set)
+    }
+
+    pub fn clip(&self, name: &str) -> Option<&Clip> {
+        self.clips.get(name)
+    }
+
+    pub fn textures(&self) -> Vec<&str> {
+        self.clips.values().map(|c| c.texture.as_str()).collect()
+    }
+
+    pub fn clips(&self) -> impl Iterator<Item = (&str, &Clip)> {
+        self.clips.iter().map(|(n, c)| (n.as_str(), c))
+    }
+}
+
+fn parse_clip(s: &Section) -> Result<Clip, String> {
+    let frames = s.required("frames")?.split(',').map(|f| {
+        let full = f.split_whitespace().next() == Some("full");
+        let v = f.split_whitespace().skip(full as usize)
+            .map(|n| s.parse_number("frames", n))
+            .collect::<Result<Vec<f32>, String>>()?;
+
+        //what follows the rectangle
+        let rest = if full { 0 } else { 4 };
+
+        if v.len() < rest || v.len() > rest + 3 {
+            return Err(format!("line {}: a frame is `x y width height|full [scale] [alpha] [rotation]`, got `{}`", s.line, f.trim()));
+        }
+
+        Ok(Frame {
+            rect: if full { None } else { Some(IntRect::new(v[0] as i32, v[1] as i32, v[2] as i32, v[3] as i32)) },
+            scale: v.get(rest).cloned().unwrap_or(1.0),
+            alpha: v.get(rest + 1).cloned().unwrap_or(255.0).max(0.0).min(255.0) as u8,
+            rotation: v.get(rest + 2).cloned().unwrap_or(0.0),
+        })
+    }).collect::<Result<Vec<Frame>, String>>()?;
+
+    let events = match s.optional("events") {
+        Some(e) => e.split(',').map(|e| {
+            let mut p = e.split_whitespace();
+
+            match (p.next().map(|f| s.parse_number("events", f)), p.next(), p.next()) {
+                (Some(f), Some(name), None) => Ok((f? as usize, name.to_string())),
+                _ => Err(format!("line {}: an event is `frame name`, got `{}`", s.line, e.trim())),
+            }
+        }).collect::<Result<Vec<_>, String>>()?,
+        None => Vec::new(),
+    };
+
+    Ok(Clip {
+        texture: s.required("texture")?.to_string(),
+        frames,
+        fps: s.parse_number("fps", &s.string("fps", "10"))?,
+        looping: s.string("loop", "true") == "true",
+        next: s.optional("next"),
+        events,
+    })
+}
+
+//plays the clips of one animation set on one sprite
+#[derive(Clone)]
+pub struct Animator {
+    clip: String,
+    frame: usize,
+    time: f32,
+    speed: f32,
+    started: bool,
+    finished: bool,
+}
+
+impl Animator {
+    pub fn new(clip: &str) -> Animator {
+        Animator {
+            clip: clip.to_string(),
+            frame: 0,
+            time: 0.0,
+            speed: 1.0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    //starts a clip from its first frame, even if it is already playing
+    pub fn play(&mut self, clip: &str) {
+        let speed = self.speed;
+        *self = Animator::new(clip);
+        self.speed = speed;
+    }
+
+    pub fn playing(&self) -> &str {
+        &self.clip
+    }
+
+    //a clip that doesn't loop and has nothing to continue with stays on its last frame
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    //2 plays twice as fast, 0 pauses
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    //returns the events of the frames that were reached
+    pub fn update(&mut self, set: &AnimationSet, d: f32) -> Vec<String> {
+        let mut events = Vec::new();
+        let clip = match set.clip(&self.clip) {
+            Some(c) => c,
+            None => return events,
+        };
+
+        if !self.started {
+            self.started = true;
+            events.extend(clip.events_at(0).cloned());
+        }
+
+        if self.finished || clip.fps <= 0.0 {
+            return events;
+        }
+
+        let frame_time = 1.0 / clip.fps;
+        self.time += d * self.speed;
+
+        while self.time >= frame_time {
+            self.time -= frame_time;
+
+            if self.frame + 1 < clip.frames.len() {
+                self.frame += 1;
+            } else if clip.looping {
+                self.frame = 0;
+            } else if let Some(ref next) = clip.next {
+                self.play(next);
+                events.extend(self.update(set, 0.0));
+                break;
+            } else {
+                self.finished = true;
+                break;
+            }
+
+            events.extend(clip.events_at(self.frame).cloned());
+        }
+
+        events
+    }
+
+    pub fn frame<'s>(&self, set: &'s AnimationSet) -> Option<&'s Frame> {
+        set.clip(&self.clip).and_then(|c| c.frames.get(self.frame))
+    }
+
+    //shows the current frame on a sprite, the tint keeps its color and gets the frame's alpha
+    pub fn apply<'a>(&self, set: &AnimationSet, am: &'a AssetManager, sprite: &mut Sprite<'a>, tint: Color) {
+        let (clip, frame) = match set.clip(&self.clip).and_then(|c| c.frames.get(self.frame).map(|f| (c, f))) {
+            Some(cf) => cf,
+            None => return,
+        };
+
+        //frames are given in the texture, which might sit somewhere in the atlas
+        let (texture, region) = am.get_region(&clip.texture);
+        let r = frame.rect_in(region.width, region.height);
+
+        sprite.set_texture(texture, false);
+        sprite.set_texture_rect(&IntRect::new(region.left + r.left, region.top + r.top, r.width, r.height));
+        sprite.set_origin(Vector2f::new(r.width as f32 / 2.0, r.height as f32 / 2.0));
+        sprite.set_scale(Vector2f::new(frame.scale, frame.scale));
+        sprite.set_color(&Color::rgba(tint.r, tint.g, tint.b, (tint.a as u32 * frame.alpha as u32 / 255) as u8));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "
+[clip]
+name = idle
+texture = tower.png
+frames = full
+
+[clip]
+name = teleport_in
+texture = tower.png
+frames = full 0.6 120, 10 20 30 40 0.8 180 90, 0 0 66 66
+fps = 30
+loop = false
+next = idle
+events = 0 pulse, 2 land
+";
+
+    #[test]
+    fn parses_clips() {
+        let set = AnimationSet::parse(SRC).unwrap();
+
+        let idle = set.clip("idle").unwrap();
+        assert_eq!(idle.frames.len(), 1);
+        assert!(idle.frames[0].rect.is_none());
+        assert_eq!(idle.fps, 10.0);
+        assert!(idle.looping);
+
+        let t = set.clip("teleport_in").unwrap();
+        assert_eq!(t.frames.len(), 3);
+        assert!(t.frames[0].rect.is_none());
+        assert_eq!((t.frames[0].scale, t.frames[0].alpha, t.frames[0].rotation), (0.6, 120, 0.0));
+        assert_eq!(t.frames[1].rect, Some(IntRect::new(10, 20, 30, 40)));
+        assert_eq!((t.frames[1].scale, t.frames[1].alpha, t.frames[1].rotation), (0.8, 180, 90.0));
+        assert_eq!((t.frames[2].scale, t.frames[2].alpha), (1.0, 255));
+        assert_eq!(t.fps, 30.0);
+        assert!(!t.looping);
+        assert_eq!(t.next.as_deref(), Some("idle"));
+        assert_eq!(t.events_at(0).collect::<Vec<_>>(), vec!["pulse"]);
+        assert_eq!(t.events_at(2).collect::<Vec<_>>(), vec!["land"]);
+    }
+
+    #[test]
+    fn shipped_clips_show_the_whole_texture() {
+        for src in &[include_str!("../Assets/tower.anim"), include_str!("../Assets/bullet.anim")] {
+            let set = AnimationSet::parse(src).unwrap();
+            assert!(set.clips().all(|(_, c)| c.frames.iter().all(|f| f.rect.is_none())));
+        }
+    }
+
+    #[test]
+    fn rejects_broken_clips() {
+        let clip = |frames: &str| format!("[clip]\nname = a\ntexture = t.png\nframes = {}\n", frames);
+
+        assert!(AnimationSet::parse(&clip("0 0 66")).is_err());
+        assert!(AnimationSet::parse(&clip("0 0 66 66 1 2 3 4")).is_err());
+        assert!(AnimationSet::parse(&clip("full 1 2 3 4")).is_err());
+        assert!(AnimationSet::parse(&clip("0 0 sixty 66")).is_err());
+        assert!(AnimationSet::parse(&(clip("full") + "next = nowhere\n")).is_err());
+        assert!(AnimationSet::parse(&(clip("full") + "events = pulse\n")).is_err());
+        assert!(AnimationSet::parse("[clip]\nname = a\nframes = full\n").is_err());
+        assert!(AnimationSet::parse("[screen]\nname = a\n").is_err());
+    }
+
+    #[test]
+    fn rects_are_cut_to_the_texture() {
+        let frame = |rect| Frame { rect, scale: 1.0, alpha: 255, rotation: 0.0 };
+
+        assert_eq!(frame(None).rect_in(40, 30), IntRect::new(0, 0, 40, 30));
+        assert_eq!(frame(Some(IntRect::new(0, 0, 66, 66))).rect_in(66, 66), IntRect::new(0, 0, 66, 66));
+        assert_eq!(frame(Some(IntRect::new(0, 0, 66, 66))).rect_in(40, 30), IntRect::new(0, 0, 40, 30));
+        assert_eq!(frame(Some(IntRect::new(20, 10, 66, 66))).rect_in(40, 30), IntRect::new(20, 10, 20, 20));
+        assert_eq!(frame(Some(IntRect::new(-5, 50, 10, 10))).rect_in(40, 30), IntRect::new(0, 30, 5, 0));
+    }
+}
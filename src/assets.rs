@@ -1,50 +1,212 @@
 use std::collections::HashMap;
+use std::time::Instant;
 use sfml::graphics::*;
-use crate::utils::get_path;
+use crate::animation::AnimationSet;
+use crate::layout::ScreenLayout;
+use crate::logging;
+use crate::script::{self, Script};
+use crate::resources::{self, Source};
 
 pub struct AssetManager {
-    res_path: String,
     fonts: HashMap<String, Font>,
     textures: HashMap<String, Texture>,
+    screens: HashMap<String, ScreenLayout>,
+    animations: HashMap<String, AnimationSet>,
+    atlas: Option<Texture>,
+    regions: HashMap<String, IntRect>, //where each texture ended up in the atlas
 }
 
+//everything the game loads at start, `validate-assets` checks the same list
+pub const ICON: &str = "icon.png";
+pub const FONTS: [&str; 1] = ["font.ttf"];
+pub const TEXTURES: [&str; 2] = ["tower.png", "bullet.png"];
+pub const ANIMATIONS: [&str; 2] = ["tower.anim", "bullet.anim"];
+pub const SCREENS: [&str; 6] = ["menu.screen", "gameover.screen", "stats.screen", "lobby.screen", "modes.screen", "crash.screen"];
+
+const ATLAS_WIDTH: u32 = 1024;
+const ATLAS_PADDING: u32 = 2; //keeps smoothing from bleeding neighbours into each other
+
 impl AssetManager {
-    pub fn new(res_path: &str) -> AssetManager {
+    pub fn new() -> AssetManager {
         AssetManager {
-            res_path: res_path.to_string(),
             fonts: HashMap::new(),
             textures: HashMap::new(),
+            screens: HashMap::new(),
+            animations: HashMap::new(),
+            atlas: None,
+            regions: HashMap::new(),
+        }
+    }
+
+    pub fn load_all(&mut self) {
+        let started = Instant::now();
+
+        for f in FONTS.iter() {
+            self.load_font(f);
         }
+        for t in TEXTURES.iter() {
+            self.load_texture(t);
+        }
+        self.build_atlas();
+        for a in ANIMATIONS.iter() {
+            self.load_animations(a);
+        }
+        for s in SCREENS.iter() {
+            self.load_screen(s);
+        }
+
+        info!(Assets, "all assets loaded in {:.1} ms", logging::ms(started));
     }
 
     pub fn load_font(&mut self, font: &str) {
+        let started = Instant::now();
+        let source = find(font);
         let f = {
-            let path = get_path((self.res_path.clone() + font).as_str());
-            if let Some(f) = Font::from_file(path.as_str()) {
+            if let Some(f) = font_from(&source) {
                 f
             } else {
-                panic!("Couldn't load font: {}", path);
+                fail(format!("Couldn't load font: {} from {}", font, source));
             }
         };
 
+        info!(Assets, "loaded {} from {} in {:.1} ms", font, source, logging::ms(started));
         self.fonts.insert(font.to_string(), f);
     }
 
     pub fn load_texture(&mut self, texture: &str) {
+        let started = Instant::now();
+        let source = find(texture);
         let t = {
-            let path = get_path((self.res_path.clone() + texture).as_str());
-            if let Some(mut t) = Texture::from_file(path.as_str()) {
+            let loaded = match source {
+                Source::File(ref p) => Texture::from_file(&p.to_string_lossy()),
+                Source::Packed(_, data) | Source::Embedded(data) => Texture::from_memory(data, &IntRect::default()),
+            };
+
+            if let Some(mut t) = loaded {
                 t.set_smooth(true);
 
                 t
             } else {
-                panic!("Couldn't load texture: {}", path);
+                fail(format!("Couldn't load texture: {} from {}", texture, source));
             }
         };
 
+        info!(Assets, "loaded {} from {} in {:.1} ms", texture, source, logging::ms(started));
         self.textures.insert(texture.to_string(), t);
     }
 
+    pub fn load_screen(&mut self, screen: &str) {
+        let started = Instant::now();
+        let (src, root) = read(screen);
+        let s = {
+            match ScreenLayout::parse(&src) {
+                Ok(s) => s,
+                Err(e) => {
+                    fail(format!("Couldn't load screen: {} from {}: {}", screen, root, e));
+                }
+            }
+        };
+
+        info!(Assets, "loaded {} from {} in {:.1} ms", screen, root, logging::ms(started));
+        self.screens.insert(screen.to_string(), s);
+    }
+
+    //packs every loaded texture into one, so sprites of different textures can be drawn in one
+    //batch. Textures are placed in rows, the tallest first, each row as high as its first one.
+    pub fn build_atlas(&mut self) {
+        let started = Instant::now();
+        let mut names: Vec<&String> = self.textures.keys().collect();
+        names.sort_by_key(|n| (std::cmp::Reverse(self.textures[*n].size().y), n.to_string()));
+
+        let (mut x, mut y, mut row) = (0, 0, 0);
+        let mut regions = HashMap::new();
+
+        for n in names {
+            let size = self.textures[n].size();
+
+            if size.x > ATLAS_WIDTH {
+                continue;
+            }
+
+            if x + size.x > ATLAS_WIDTH {
+                x = 0;
+                y += row + ATLAS_PADDING;
+                row = 0;
+            }
+
+            regions.insert(n.clone(), IntRect::new(x as i32, y as i32, size.x as i32, size.y as i32));
+            x += size.x + ATLAS_PADDING;
+            row = row.max(size.y);
+        }
+
+        let mut image = Image::new(ATLAS_WIDTH, (y + row).max(1));
+
+        for (n, r) in &regions {
+            match self.textures[n].copy_to_image() {
+                Some(i) => {
+                    let source = IntRect::new(0, 0, r.width, r.height);
+                    image.copy_image(&i, r.left as u32, r.top as u32, &source, false);
+                }
+                None => fail(format!("Couldn't copy {} into the texture atlas", n)),
+            }
+        }
+
+        match Texture::from_image(&image) {
+            Some(mut t) => {
+                t.set_smooth(true);
+                info!(Assets, "packed {} textures into a {}x{} atlas in {:.1} ms",
+                      regions.len(), image.size().x, image.size().y, logging::ms(started));
+                self.atlas = Some(t);
+                self.regions = regions;
+            }
+            None => fail("Couldn't create the texture atlas".to_string()),
+        }
+    }
+
+    //the texture to draw a loaded texture from and where it is in there,
+    //which is the atlas once it was built
+    pub fn get_region(&self, texture: &str) -> (&Texture, IntRect) {
+        if let (Some(a), Some(r)) = (self.atlas.as_ref(), self.regions.get(texture)) {
+            return (a, *r);
+        }
+
+        let t = self.get_texture(texture);
+        (t, IntRect::new(0, 0, t.size().x as i32, t.size().y as i32))
+    }
+
+    //the textures the clips play have to be loaded already
+    pub fn load_animations(&mut self, animations: &str) {
+        let started = Instant::now();
+        let (src, root) = read(animations);
+        let a = {
+            match AnimationSet::parse(&src) {
+                Ok(a) => a,
+                Err(e) => {
+                    fail(format!("Couldn't load animations: {} from {}: {}", animations, root, e));
+                }
+            }
+        };
+
+        for t in a.textures() {
+            if !self.textures.contains_key(t) {
+                fail(format!("Couldn't load animations: {}: texture {} isn't loaded", animations, t));
+            }
+        }
+
+        //a mod may have replaced the texture with a smaller one
+        for (name, c) in a.clips() {
+            let size = self.get_texture(&c.texture).size();
+            let cut = c.frames.iter().any(|f| f.rect.map_or(false, |r| f.rect_in(size.x as i32, size.y as i32) != r));
+
+            if cut {
+                warn!(Assets, "{}: frames of `{}` reach outside {}, they are cut to it", animations, name, c.texture);
+            }
+        }
+
+        info!(Assets, "loaded {} from {} in {:.1} ms", animations, root, logging::ms(started));
+        self.animations.insert(animations.to_string(), a);
+    }
+
     pub fn get_font(&self, font: &str) -> &Font {
         if let Some(f) = self.fonts.get(font) {
             return f;
@@ -60,5 +222,115 @@ impl AssetManager {
             panic!("Texture not loaded: {}", texture);
         }
     }
+
+    pub fn get_animations(&self, animations: &str) -> &AnimationSet {
+        if let Some(a) = self.animations.get(animations) {
+            return a;
+        } else {
+            panic!("Animations not loaded: {}", animations);
+        }
+    }
+
+    pub fn get_screen(&self, screen: &str) -> &ScreenLayout {
+        if let Some(s) = self.screens.get(screen) {
+            return s;
+        } else {
+            panic!("Screen not loaded: {}", screen);
+        }
+    }
 }
 
+//tries every asset and script without a window, all problems are listed instead of stopping at
+//the first. Images are only decoded, textures need a graphics context.
+pub fn validate() -> Result<String, String> {
+    let mut problems = Vec::new();
+    let mut checked = 0;
+    let mut check = |name: &str, result: Result<(), String>| {
+        checked += 1;
+        if let Err(e) = result {
+            problems.push(format!("{}: {}", name, e));
+        }
+    };
+
+    let file = |name: &str| resources::find(name).ok_or_else(|| resources::not_found(name));
+    let text = |name: &str| resources::read_to_string(name).map(|(s, _)| s);
+
+    for f in FONTS.iter() {
+        check(f, file(f).and_then(|s| font_from(&s).map(|_| ()).ok_or(format!("the one from {} is not a font", s))));
+    }
+    for i in [ICON].iter().chain(TEXTURES.iter()) {
+        check(i, file(i).and_then(|s| image_from(&s).map(|_| ()).ok_or(format!("the one from {} is not an image", s))));
+    }
+    for a in ANIMATIONS.iter() {
+        check(a, text(a).and_then(|s| AnimationSet::parse(&s)).and_then(|set| {
+            match set.textures().into_iter().find(|t| !TEXTURES.contains(t)) {
+                Some(t) => Err(format!("texture {} isn't loaded", t)),
+                None => Ok(()),
+            }
+        }));
+    }
+    for s in SCREENS.iter() {
+        check(s, text(s).and_then(|s| ScreenLayout::parse(&s)).map(|_| ()));
+    }
+
+    //script errors already say which script it was, and broken packs which pack it was
+    for name in script::available() {
+        checked += 1;
+        if let Err(e) = Script::load(&name, 0) {
+            problems.push(e);
+        }
+    }
+    problems.extend(resources::broken_packs());
+
+    if problems.is_empty() {
+        Ok(format!("{} assets and scripts are fine", checked))
+    } else {
+        Err(format!("{} problems with the {} assets and scripts:\n{}", problems.len(), checked, problems.join("\n")))
+    }
+}
+
+//the window keeps the default icon without it
+pub fn load_icon() -> Option<Image> {
+    let icon = resources::find(ICON).and_then(|s| image_from(&s));
+
+    if icon.is_none() {
+        warn!(Assets, "couldn't load the icon: {}", resources::not_found(ICON));
+    }
+
+    icon
+}
+
+fn font_from(source: &Source) -> Option<Font> {
+    match source {
+        Source::File(p) => Font::from_file(&p.to_string_lossy()),
+        Source::Packed(_, data) | Source::Embedded(data) => Font::from_memory(data),
+    }
+}
+
+fn image_from(source: &Source) -> Option<Image> {
+    match source {
+        Source::File(p) => Image::from_file(&p.to_string_lossy()),
+        Source::Packed(_, data) | Source::Embedded(data) => Image::from_memory(data),
+    }
+}
+
+//the first resource root that has the asset, or the binary
+fn find(name: &str) -> Source {
+    match resources::find(name) {
+        Some(s) => s,
+        None => fail(format!("Couldn't load {}", resources::not_found(name))),
+    }
+}
+
+fn read(name: &str) -> (String, resources::Root) {
+    match resources::read_to_string(name) {
+        Ok(r) => r,
+        Err(e) => fail(format!("Couldn't load {}", e)),
+    }
+}
+
+//a missing asset can't be worked around, the log says which one it was
+fn fail(message: String) -> ! {
+    error!(Assets, "{}", message);
+    panic!("{}", message);
+}
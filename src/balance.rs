@@ -0,0 +1,351 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::bots;
+use crate::sim::Rules;
+
+// `moving-tower balance` lets a bot play thousands of headless runs for every combination
+// of the given rules and writes how long the runs survived and what they scored to
+// <out>.csv and <out>.json. Every option takes a list (`80,100,120`) or a range (`80:140:20`)
+// of at most 1000 values, and a sweep plays at most 10000 combinations:
+//
+//   --inner, --outer, --speed, --per-bullet, --falloff   the rules to sweep
+//   --bot random|greedy|lookahead   who plays (greedy)
+//   --games n                       runs per combination (200)
+//   --max-time seconds              runs are cut off after this (300)
+//   --out path                      where the reports go, without extension (balance)
+
+const HISTOGRAM_BUCKETS: usize = 10;
+const MAX_VALUES: u64 = 1000; //per rule, so a tiny step in a range doesn't fill the memory
+const MAX_COMBINATIONS: u64 = 10_000;
+
+pub struct Sweep {
+    inner: Vec<f32>,
+    outer: Vec<f32>,
+    speed: Vec<f32>,
+    per_bullet: Vec<f32>,
+    falloff: Vec<f32>,
+    bot: String,
+    games: u64,
+    max_duration: f32,
+    out: String,
+}
+
+impl Sweep {
+    pub fn from_args(args: &[String]) -> Result<Sweep, String> {
+        let d = Rules::default();
+        let mut s = Sweep {
+            inner: vec![d.tower_inner],
+            outer: vec![d.tower_outer],
+            speed: vec![d.bullet_speed],
+            per_bullet: vec![d.score_per_bullet],
+            falloff: vec![d.score_falloff],
+            bot: "greedy".to_string(),
+            games: 200,
+            max_duration: 300.0,
+            out: "balance".to_string(),
+        };
+
+        let mut i = 0;
+        while i < args.len() {
+            let value = args.get(i + 1).ok_or(format!("{} needs a value", args[i]))?;
+
+            match args[i].as_str() {
+                "--inner" => s.inner = parse_values(value)?,
+                "--outer" => s.outer = parse_values(value)?,
+                "--speed" => s.speed = parse_values(value)?,
+                "--per-bullet" => s.per_bullet = parse_values(value)?,
+                "--falloff" => s.falloff = parse_values(value)?,
+                "--bot" => s.bot = value.clone(),
+                "--games" => s.games = value.parse().map_err(|_| format!("Not a number of games: {}", value))?,
+                "--max-time" => s.max_duration = value.parse().map_err(|_| format!("Not a time: {}", value))?,
+                "--out" => s.out = value.clone(),
+                o => {
+                    return Err(format!("Unknown option {}", o));
+                }
+            }
+
+            i += 2;
+        }
+
+        if bots::by_name(&s.bot, 0).is_none() {
+            return Err(format!("Unknown bot {}, there are: {}", s.bot, bots::BOT_NAMES.join(", ")));
+        }
+
+        let count = s.count();
+        if count > MAX_COMBINATIONS {
+            return Err(format!("That makes {} combinations, at most {} can be played at once", count, MAX_COMBINATIONS));
+        }
+
+        Ok(s)
+    }
+
+    //how many combinations there are, without making them
+    fn count(&self) -> u64 {
+        let rings = self.inner.iter().map(|i| self.outer.iter().filter(|o| i <= o).count() as u64).sum::<u64>();
+        rings * self.speed.len() as u64 * self.per_bullet.len() as u64 * self.falloff.len() as u64
+    }
+
+    //every combination, leaving out rings whose inner edge lies outside the outer one
+    fn combinations(&self) -> Vec<Rules> {
+        let mut c = Vec::new();
+
+        for &tower_inner in &self.inner {
+            for &tower_outer in &self.outer {
+                if tower_inner > tower_outer {
+                    continue;
+                }
+
+                for &bullet_speed in &self.speed {
+                    for &score_per_bullet in &self.per_bullet {
+                        for &score_falloff in &self.falloff {
+                            c.push(Rules {
+                                tower_inner,
+                                tower_outer,
+                                bullet_speed,
+                                score_per_bullet,
+                                score_falloff,
+                                ..Rules::default()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        c
+    }
+}
+
+fn parse_values(s: &str) -> Result<Vec<f32>, String> {
+    let error = || format!("Expected a list like 80,100 or a range like 80:140:20, got {}", s);
+
+    if s.contains(':') {
+        let p: Vec<f32> = s.split(':').map(|p| p.trim().parse::<f32>()).collect::<Result<_, _>>().map_err(|_| error())?;
+
+        if p.len() != 3 || p.iter().any(|v| !v.is_finite()) || p[2] <= 0.0 || p[1] < p[0] {
+            return Err(error());
+        }
+
+        //counted before anything is allocated
+        let steps = ((p[1] as f64 - p[0] as f64) / p[2] as f64 + 0.001).floor();
+        if steps >= MAX_VALUES as f64 {
+            return Err(format!("{} makes {} values, at most {} are allowed", s, steps + 1.0, MAX_VALUES));
+        }
+
+        Ok((0..=steps as u32).map(|i| p[0] + p[2] * i as f32).collect())
+    } else {
+        let values: Vec<f32> = s.split(',').map(|p| p.trim().parse::<f32>()).collect::<Result<_, _>>().map_err(|_| error())?;
+
+        if values.len() as u64 > MAX_VALUES {
+            return Err(format!("{} values, at most {} are allowed", values.len(), MAX_VALUES));
+        }
+
+        Ok(values)
+    }
+}
+
+struct Distribution {
+    mean: f32,
+    min: f32,
+    p10: f32,
+    median: f32,
+    p90: f32,
+    max: f32,
+    histogram: Vec<u32>, //equal buckets from 0 to max
+}
+
+impl Distribution {
+    fn new(mut values: Vec<f32>) -> Distribution {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let n = values.len().max(1);
+        let at = |q: f32| values.get(((n - 1) as f32 * q).round() as usize).cloned().unwrap_or(0.0);
+        let max = at(1.0);
+
+        let mut histogram = vec![0; HISTOGRAM_BUCKETS];
+        for v in &values {
+            let b = if max > 0.0 { (v / max * HISTOGRAM_BUCKETS as f32) as usize } else { 0 };
+            histogram[b.min(HISTOGRAM_BUCKETS - 1)] += 1;
+        }
+
+        Distribution {
+            mean: values.iter().sum::<f32>() / n as f32,
+            min: at(0.0),
+            p10: at(0.1),
+            median: at(0.5),
+            p90: at(0.9),
+            max,
+            histogram,
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let h: Vec<String> = self.histogram.iter().map(|c| c.to_string()).collect();
+
+        format!("{{\"mean\":{:.2},\"min\":{:.2},\"p10\":{:.2},\"median\":{:.2},\"p90\":{:.2},\"max\":{:.2},\"histogram\":[{}]}}",
+                self.mean, self.min, self.p10, self.median, self.p90, self.max, h.join(","))
+    }
+}
+
+struct Outcome {
+    rules: Rules,
+    survival: Distribution,
+    score: Distribution,
+    capped: u32, //runs that were still alive at the time limit
+}
+
+fn play(sweep: &Sweep, rules: Rules) -> Outcome {
+    let mut times = Vec::new();
+    let mut scores = Vec::new();
+    let mut capped = 0;
+
+    for seed in 1..=sweep.games {
+        if let Some(mut bot) = bots::by_name(&sweep.bot, seed) {
+            let run = bots::play_headless(&mut *bot, rules, seed, sweep.max_duration);
+
+            if run.duration >= sweep.max_duration {
+                capped += 1;
+            }
+
+            times.push(run.duration);
+            scores.push(run.score);
+        }
+    }
+
+    Outcome {
+        rules,
+        survival: Distribution::new(times),
+        score: Distribution::new(scores),
+        capped,
+    }
+}
+
+//plays every combination on all cores and writes the reports, returns a short summary
+pub fn run(sweep: Sweep) -> Result<String, String> {
+    let combinations = Arc::new(sweep.combinations());
+    let sweep = Arc::new(sweep);
+    let next = Arc::new(AtomicUsize::new(0));
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+
+    if combinations.is_empty() {
+        return Err("Nothing to play, every inner radius is larger than the outer ones".to_string());
+    }
+
+    println!("{} combinations, {} runs each with the {} bot", combinations.len(), sweep.games, sweep.bot);
+
+    let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(combinations.len());
+    let handles: Vec<_> = (0..workers).map(|_| {
+        let (combinations, sweep, next, outcomes) = (combinations.clone(), sweep.clone(), next.clone(), outcomes.clone());
+
+        thread::spawn(move || {
+            loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let rules = match combinations.get(i) {
+                    Some(r) => *r,
+                    None => break,
+                };
+
+                let o = play(&sweep, rules);
+                println!("inner {} outer {} speed {} per bullet {} falloff {}: median {:.1}s, median score {:.0}",
+                         rules.tower_inner, rules.tower_outer, rules.bullet_speed, rules.score_per_bullet,
+                         rules.score_falloff, o.survival.median, o.score.median);
+
+                if let Ok(mut v) = outcomes.lock() {
+                    v.push((i, o));
+                }
+            }
+        })
+    }).collect();
+
+    for h in handles {
+        h.join().map_err(|_| "A simulation thread crashed".to_string())?;
+    }
+
+    let mut outcomes = match Arc::try_unwrap(outcomes) {
+        Ok(m) => m.into_inner().map_err(|_| "A simulation thread crashed".to_string())?,
+        Err(_) => return Err("The simulation threads are still running".to_string()),
+    };
+    outcomes.sort_by_key(|(i, _)| *i);
+    let outcomes: Vec<Outcome> = outcomes.into_iter().map(|(_, o)| o).collect();
+
+    write_csv(&format!("{}.csv", sweep.out), &outcomes).map_err(|e| e.to_string())?;
+    write_json(&format!("{}.json", sweep.out), &sweep, &outcomes).map_err(|e| e.to_string())?;
+
+    Ok(format!("Wrote {0}.csv and {0}.json", sweep.out))
+}
+
+fn write_csv(path: &str, outcomes: &[Outcome]) -> std::io::Result<()> {
+    let mut f = File::create(path)?;
+
+    writeln!(f, "tower_inner,tower_outer,bullet_speed,score_per_bullet,score_falloff,\
+                 mean_time,p10_time,median_time,p90_time,max_time,capped,\
+                 mean_score,p10_score,median_score,p90_score,max_score")?;
+
+    for o in outcomes {
+        let (r, t, s) = (&o.rules, &o.survival, &o.score);
+
+        writeln!(f, "{},{},{},{},{},{:.2},{:.2},{:.2},{:.2},{:.2},{},{:.1},{:.1},{:.1},{:.1},{:.1}",
+                 r.tower_inner, r.tower_outer, r.bullet_speed, r.score_per_bullet, r.score_falloff,
+                 t.mean, t.p10, t.median, t.p90, t.max, o.capped,
+                 s.mean, s.p10, s.median, s.p90, s.max)?;
+    }
+
+    f.flush()
+}
+
+fn write_json(path: &str, sweep: &Sweep, outcomes: &[Outcome]) -> std::io::Result<()> {
+    let results: Vec<String> = outcomes.iter().map(|o| {
+        let r = &o.rules;
+
+        format!("    {{\"tower_inner\":{},\"tower_outer\":{},\"bullet_speed\":{},\"score_per_bullet\":{},\"score_falloff\":{},\
+                 \"capped\":{},\"survival\":{},\"score\":{}}}",
+                r.tower_inner, r.tower_outer, r.bullet_speed, r.score_per_bullet, r.score_falloff,
+                o.capped, o.survival.to_json(), o.score.to_json())
+    }).collect();
+
+    let mut f = File::create(path)?;
+
+    writeln!(f, "{{\n  \"bot\": \"{}\",\n  \"games\": {},\n  \"max_time\": {},\n  \"results\": [\n{}\n  ]\n}}",
+             sweep.bot, sweep.games, sweep.max_duration, results.join(",\n"))?;
+
+    f.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_lists_and_ranges() {
+        assert_eq!(parse_values("80, 100,120").unwrap(), vec![80.0, 100.0, 120.0]);
+        assert_eq!(parse_values("80:140:20").unwrap(), vec![80.0, 100.0, 120.0, 140.0]);
+        assert_eq!(parse_values("0:0.3:0.1").unwrap().len(), 4);
+
+        for broken in &["80:140", "140:80:20", "80:140:0", "80:140:-5", "80:inf:1", "nan:1:1", "80,x", ""] {
+            assert!(parse_values(broken).is_err(), "{}", broken);
+        }
+    }
+
+    #[test]
+    fn caps_the_size_of_sweeps() {
+        let e = parse_values("80:140:0.00001").err().unwrap_or_default();
+        assert!(e.contains("6000001 values"), "{}", e);
+        assert!(parse_values("0:999:1").is_ok());
+
+        let e = Sweep::from_args(&args("--inner 0:99:1 --outer 100:199:1 --speed 1,2")).err().unwrap_or_default();
+        assert!(e.contains("20000 combinations"), "{}", e);
+
+        //rings that are left out don't count
+        let s = Sweep::from_args(&args("--inner 0:99:1 --outer 0:99:1")).unwrap();
+        assert_eq!(s.count(), 5050);
+        assert_eq!(s.combinations().len(), 5050);
+    }
+}
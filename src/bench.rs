@@ -0,0 +1,147 @@
+use std::time::Instant;
+
+use sfml::system::Vector2f;
+
+use crate::bullets::BulletStore;
+use crate::sim::{Circle, Rules, BULLET_RADIUS, NEAR_MISS_DISTANCE, TOWER_RADIUS};
+use crate::utils::Rng;
+
+// `moving-tower bench [bullets]` times the bullet update without a window. It compares the
+// bullet store against the one struct per bullet layout it replaced, which is kept here
+// only for the comparison, and checks that both end up with exactly the same bullets.
+// Without a number it runs with 1000, 10000 and 100000 bullets. Built with the `parallel`
+// feature it also times the store on all cores.
+
+pub const DEFAULT_BENCH_BULLETS: [usize; 3] = [1_000, 10_000, 100_000];
+const FRAMES: usize = 600;
+const DELTA: f32 = 1.0 / 60.0;
+
+//how bullets used to be stored, every update works out the direction again
+struct OldBullet {
+    hitbox: Circle,
+    position: Vector2f,
+    rotation: f32,
+    speed: f32,
+    arena: Vector2f,
+    near: bool,
+}
+
+impl OldBullet {
+    fn update(&mut self, d: f32) {
+        if self.position.x <= 0.0 || self.position.x >= self.arena.x {
+            self.rotation = -self.rotation + 180.0;
+        }
+
+        if self.position.y <= 0.0 || self.position.y >= self.arena.y {
+            self.rotation = -self.rotation;
+        }
+
+        self.position.x += self.rotation.to_radians().cos() * self.speed * d;
+        self.position.y += self.rotation.to_radians().sin() * self.speed * d;
+
+        self.hitbox.x = self.position.x;
+        self.hitbox.y = self.position.y;
+    }
+}
+
+fn update_old(bullets: &mut [OldBullet], tower: &Circle) {
+    for b in bullets.iter_mut() {
+        b.update(DELTA);
+
+        let gap = b.hitbox.gap(tower).max(0.0);
+        if gap < NEAR_MISS_DISTANCE {
+            b.near = true;
+        } else if b.near {
+            b.near = false;
+        }
+    }
+}
+
+//the same bullets in both layouts
+fn setup(count: usize, rules: &Rules) -> (Vec<OldBullet>, BulletStore) {
+    let mut rng = Rng::new(1);
+    let mut old = Vec::with_capacity(count);
+    let mut store = BulletStore::new();
+    let arena = Vector2f::new(rules.arena_width, rules.arena_height);
+
+    for _ in 0..count {
+        let position = Vector2f::new(rng.range(1.0, arena.x - 1.0), rng.range(1.0, arena.y - 1.0));
+        let rotation = rng.range(-180.0, 180.0);
+
+        old.push(OldBullet {
+            hitbox: Circle::new(position.x, position.y, BULLET_RADIUS),
+            position,
+            rotation,
+            speed: rules.bullet_speed,
+            arena,
+            near: false,
+        });
+        store.insert(position, rotation, BULLET_RADIUS, rules.bullet_speed);
+    }
+
+    (old, store)
+}
+
+//milliseconds per frame
+fn time<F: FnMut()>(mut f: F) -> f32 {
+    let start = Instant::now();
+
+    for _ in 0..FRAMES {
+        f();
+    }
+
+    start.elapsed().as_secs_f32() * 1000.0 / FRAMES as f32
+}
+
+fn same(old: &[OldBullet], store: &BulletStore) -> bool {
+    old.len() == store.len() && old.iter().zip(store.iter()).all(|(a, b)| {
+        a.position.x.to_bits() == b.position.x.to_bits()
+            && a.position.y.to_bits() == b.position.y.to_bits()
+            && a.rotation.to_bits() == b.rotation.to_bits()
+    })
+}
+
+#[cfg(feature = "parallel")]
+fn parallel(count: usize, tower: &Circle, old: &[OldBullet], old_ms: f32) -> Result<String, String> {
+    let (_, mut store) = setup(count, &Rules::default());
+    let ms = time(|| {
+        store.update_parallel(DELTA, tower);
+    });
+
+    if !same(old, &store) {
+        return Err(format!("{} bullets: the parallel update ended up somewhere else", count));
+    }
+
+    Ok(format!(", parallel {:.3} ms ({:.1}x)", ms, old_ms / ms))
+}
+
+#[cfg(not(feature = "parallel"))]
+fn parallel(_count: usize, _tower: &Circle, _old: &[OldBullet], _old_ms: f32) -> Result<String, String> {
+    Ok(String::new())
+}
+
+pub fn run(counts: &[usize]) -> Result<String, String> {
+    let rules = Rules::default();
+    let tower = Circle::new(rules.arena_width / 2.0, rules.arena_height / 2.0, TOWER_RADIUS);
+    let mut lines = Vec::new();
+
+    for &count in counts {
+        let (mut old, mut store) = setup(count, &rules);
+
+        let old_ms = time(|| {
+            update_old(&mut old, &tower);
+        });
+        let store_ms = time(|| {
+            store.update_sequential(DELTA, &tower);
+        });
+
+        if !same(&old, &store) {
+            return Err(format!("{} bullets: the bullet store ended up somewhere else than the old layout", count));
+        }
+
+        lines.push(format!("{:>7} bullets: old {:.3} ms, store {:.3} ms ({:.1}x){}",
+                           count, old_ms, store_ms, old_ms / store_ms, parallel(count, &tower, &old, old_ms)?));
+    }
+
+    Ok(format!("{} frames each\n{}", FRAMES, lines.join("\n")))
+}
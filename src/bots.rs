@@ -0,0 +1,285 @@
+use std::f32::consts::PI;
+
+use crate::gym::Env;
+use crate::net::FIXED_DELTA;
+use crate::sim::*;
+use crate::stats::RunStats;
+use crate::utils::Rng;
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// Bots play by the same rules as the mouse: every frame they pick a point to aim at
+// and may teleport towards it, which also fires the new bullet at that point.
+
+#[derive(Clone, Copy)]
+pub struct Decision {
+    pub target: (f32, f32),
+    pub teleport: bool,
+}
+
+impl Decision {
+    pub fn inputs(&self) -> Vec<TowerInput> {
+        let (x, y) = self.target;
+        let mut inputs = vec![TowerInput::Aim(x, y)];
+
+        if self.teleport {
+            inputs.push(TowerInput::Teleport(x, y));
+        }
+
+        inputs
+    }
+}
+
+pub trait Controller {
+    fn name(&self) -> &'static str;
+
+    //called once per frame after the tower was updated, just like the mouse events are
+    fn think(&mut self, tower: &TowerState, d: f32) -> Decision;
+}
+
+pub const BOT_NAMES: [&str; 3] = ["random", "greedy", "lookahead"];
+
+pub fn by_name(name: &str, seed: u64) -> Option<Box<Controller>> {
+    match name {
+        "random" => Some(Box::new(RandomBot::new(seed))),
+        "greedy" => Some(Box::new(GreedyBot::new())),
+        "lookahead" => Some(Box::new(LookaheadBot::new())),
+        _ => None,
+    }
+}
+
+const DIRECTIONS: u32 = 16;
+const TELEPORT_CHANCE: f32 = 0.8; //per second, for the random bot
+const DANGER_GAP: f32 = 60.0;
+const MAX_IDLE: f32 = 1.5; //bots teleport at least this often, standing still scores nothing
+const PREDICT_TIME: f32 = 0.3;
+const THINK_INTERVAL: f32 = 0.1;
+const HORIZON: f32 = 2.0;
+const HORIZON_STEP: f32 = 0.05;
+
+//teleport targets all around the tower at the shortest, medium and longest distance,
+//only those that keep the tower inside the arena
+fn candidates(tower: &TowerState) -> Vec<(f32, f32)> {
+    let rules = tower.rules();
+    let mut c = Vec::new();
+
+    for i in 0..DIRECTIONS {
+        let a = i as f32 / DIRECTIONS as f32 * 2.0 * PI;
+
+        for l in &[rules.tower_inner, (rules.tower_inner + rules.tower_outer) / 2.0, rules.tower_outer] {
+            let x = tower.position.x + a.cos() * l;
+            let y = tower.position.y + a.sin() * l;
+
+            if x > TOWER_RADIUS && x < rules.arena_width - TOWER_RADIUS && y > TOWER_RADIUS && y < rules.arena_height - TOWER_RADIUS {
+                c.push((x, y));
+            }
+        }
+    }
+
+    c
+}
+
+//smallest gap between a point and where the bullets of the tower will be in `ahead` seconds
+fn clearance(x: f32, y: f32, tower: &TowerState, ahead: f32) -> f32 {
+    let speed = tower.rules().bullet_speed;
+
+    tower.bullets.iter().map(|b| {
+        let bx = b.position.x + b.rotation.to_radians().cos() * speed * ahead;
+        let by = b.position.y + b.rotation.to_radians().sin() * speed * ahead;
+
+        f32::sqrt((bx - x).powi(2) + (by - y).powi(2)) - TOWER_RADIUS - BULLET_RADIUS
+    }).fold(std::f32::INFINITY, f32::min)
+}
+
+pub struct RandomBot {
+    rng: Rng,
+    target: (f32, f32),
+}
+
+impl RandomBot {
+    pub fn new(seed: u64) -> RandomBot {
+        RandomBot {
+            rng: Rng::new(seed),
+            target: (WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0),
+        }
+    }
+}
+
+impl Controller for RandomBot {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+
+    fn think(&mut self, tower: &TowerState, d: f32) -> Decision {
+        let teleport = self.rng.next_f32() < TELEPORT_CHANCE * d;
+
+        if teleport {
+            let rules = tower.rules();
+            self.target = (self.rng.range(0.0, rules.arena_width), self.rng.range(0.0, rules.arena_height));
+        }
+
+        Decision {
+            target: self.target,
+            teleport,
+        }
+    }
+}
+
+//teleports away when a bullet comes close, to the spot farthest from all bullets
+pub struct GreedyBot {
+    idle: f32,
+}
+
+impl GreedyBot {
+    pub fn new() -> GreedyBot {
+        GreedyBot {
+            idle: 0.0,
+        }
+    }
+}
+
+impl Controller for GreedyBot {
+    fn name(&self) -> &'static str {
+        "greedy"
+    }
+
+    fn think(&mut self, tower: &TowerState, d: f32) -> Decision {
+        self.idle += d;
+
+        let here = clearance(tower.position.x, tower.position.y, tower, PREDICT_TIME);
+        let best = candidates(tower).into_iter()
+            .map(|(x, y)| ((x, y), clearance(x, y, tower, PREDICT_TIME)))
+            .fold(None, |best: Option<((f32, f32), f32)>, c| match best {
+                Some(b) if b.1 >= c.1 => Some(b),
+                _ => Some(c),
+            });
+
+        let (target, gap) = match best {
+            Some(b) => b,
+            None => ((WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0), 0.0),
+        };
+
+        let teleport = (here < DANGER_GAP || self.idle > MAX_IDLE) && gap > here.min(DANGER_GAP);
+
+        if teleport {
+            self.idle = 0.0;
+        }
+
+        Decision {
+            target,
+            teleport,
+        }
+    }
+}
+
+//tries every teleport and standing still on a copy of the game a couple of seconds ahead
+//and takes what survives longest, with the most room to spare
+pub struct LookaheadBot {
+    target: (f32, f32),
+    cooldown: f32,
+    idle: f32,
+}
+
+impl LookaheadBot {
+    pub fn new() -> LookaheadBot {
+        LookaheadBot {
+            target: (WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0),
+            cooldown: 0.0,
+            idle: 0.0,
+        }
+    }
+
+    //how long the tower survives and how close the bullets got until then
+    fn outlook(tower: &TowerState, teleport: Option<(f32, f32)>) -> (f32, f32) {
+        let mut t = tower.clone();
+
+        if let Some((x, y)) = teleport {
+            t.input(TowerInput::Aim(x, y));
+            t.input(TowerInput::Teleport(x, y));
+        }
+
+        let mut time = 0.0;
+        let mut gap = clearance(t.position.x, t.position.y, &t, 0.0);
+        let step = t.rules().bullet_speed * HORIZON_STEP;
+
+        //the steps are coarse, so a bullet closer than one step counts as a hit
+        while time < HORIZON && gap > step {
+            t.update(HORIZON_STEP);
+            time += HORIZON_STEP;
+
+            if t.dead {
+                return (time, 0.0);
+            }
+
+            gap = gap.min(clearance(t.position.x, t.position.y, &t, 0.0));
+        }
+
+        if gap <= step {
+            return (time, 0.0);
+        }
+
+        (time, gap.min(t.rules().tower_outer))
+    }
+}
+
+impl Controller for LookaheadBot {
+    fn name(&self) -> &'static str {
+        "lookahead"
+    }
+
+    fn think(&mut self, tower: &TowerState, d: f32) -> Decision {
+        self.cooldown -= d;
+        self.idle += d;
+
+        if self.cooldown > 0.0 {
+            return Decision {
+                target: self.target,
+                teleport: false,
+            };
+        }
+
+        self.cooldown = THINK_INTERVAL;
+
+        let stay = LookaheadBot::outlook(tower, None);
+        let best = candidates(tower).into_iter()
+            .map(|c| (c, LookaheadBot::outlook(tower, Some(c))))
+            .fold(None, |best: Option<((f32, f32), (f32, f32))>, c| match best {
+                Some(b) if (b.1).0 > (c.1).0 || ((b.1).0 == (c.1).0 && (b.1).1 >= (c.1).1) => Some(b),
+                _ => Some(c),
+            });
+
+        let mut teleport = false;
+
+        if let Some((target, (time, gap))) = best {
+            self.target = target;
+
+            let threatened = stay.0 < HORIZON || stay.1 < DANGER_GAP;
+            let safer = time > stay.0 || (time == stay.0 && gap > stay.1);
+            let bored = self.idle > MAX_IDLE && time >= HORIZON;
+
+            teleport = (threatened && safer) || bored;
+        }
+
+        if teleport {
+            self.idle = 0.0;
+        }
+
+        Decision {
+            target: self.target,
+            teleport,
+        }
+    }
+}
+
+//plays a whole run without a window
+pub fn play_headless(bot: &mut Controller, rules: Rules, seed: u64, max_duration: f32) -> RunStats {
+    let mut env = Env::new().with_rules(rules).with_max_duration(max_duration);
+    env.reset(seed);
+
+    while !env.done() {
+        let decision = bot.think(env.tower(), FIXED_DELTA);
+        env.step(decision);
+    }
+
+    env.stats().clone()
+}
@@ -0,0 +1,368 @@
+use std::env;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sfml::system::Vector2f;
+
+use crate::net::{read_u32, with_default_port, FIXED_DELTA};
+use crate::scenes::score_rate;
+use crate::sim::{TowerInput, TowerState};
+use crate::utils::Rng;
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// Lets others watch a run live, for example on a big screen during a tournament. The game sends
+// everything needed to draw the current frame to every spectator over tcp, spectators only draw
+// what they receive and never simulate anything themselves.
+// Broadcasting is turned on with MOVING_TOWER_BROADCAST=port, left empty the default port is used.
+
+pub const DEFAULT_BROADCAST_PORT: u16 = 7778;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_BACKLOG: usize = 1 << 20; //spectators that fall this far behind are dropped
+const MAX_FRAME: usize = 1 << 24;
+
+const MAGIC: [u8; 4] = *b"MTB1";
+const IDLE: u8 = 0; //nobody is playing, the game is in a menu
+const RUN: u8 = 1;
+
+#[derive(Clone)]
+pub struct Snapshot {
+    pub score: f32,
+    pub tower: TowerState,
+}
+
+//every frame is prefixed with its length since tcp is a stream
+fn encode(snapshot: Option<&Snapshot>) -> Vec<u8> {
+    let mut payload = MAGIC.to_vec();
+
+    match snapshot {
+        Some(s) => {
+            payload.push(RUN);
+            payload.extend_from_slice(&s.score.to_le_bytes());
+            s.tower.encode(&mut payload);
+        }
+        None => payload.push(IDLE),
+    }
+
+    let mut frame = (payload.len() as u32).to_le_bytes().to_vec();
+    frame.extend_from_slice(&payload);
+
+    frame
+}
+
+//the outer option is None for frames we don't understand
+fn decode(payload: &[u8]) -> Option<Option<Snapshot>> {
+    if payload.len() < 5 || payload[..4] != MAGIC {
+        return None;
+    }
+
+    match payload[4] {
+        IDLE => Some(None),
+        RUN => {
+            if payload.len() < 9 {
+                return None;
+            }
+
+            let score = f32::from_bits(read_u32(&payload[5..]));
+            let (tower, _) = TowerState::decode(&payload[9..])?;
+
+            Some(Some(Snapshot { score, tower }))
+        }
+
+        _ => None,
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    backlog: Vec<u8>,
+}
+
+impl Client {
+    //returns false when the spectator is gone or can't keep up
+    fn push(&mut self, frame: &[u8]) -> bool {
+        if self.backlog.len() + frame.len() > MAX_BACKLOG {
+            return false;
+        }
+
+        self.backlog.extend_from_slice(frame);
+        self.flush()
+    }
+
+    fn flush(&mut self) -> bool {
+        while !self.backlog.is_empty() {
+            match self.stream.write(&self.backlog) {
+                Ok(0) => return false,
+                Ok(n) => {
+                    self.backlog.drain(..n);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return false,
+            }
+        }
+
+        true
+    }
+}
+
+pub struct Broadcaster {
+    listener: TcpListener,
+    clients: Vec<Client>,
+}
+
+impl Broadcaster {
+    pub fn bind(port: u16) -> io::Result<Broadcaster> {
+        let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Broadcaster {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    pub fn from_env() -> Option<Broadcaster> {
+        let v = env::var("MOVING_TOWER_BROADCAST").ok()?;
+        let port = if v.trim().is_empty() { DEFAULT_BROADCAST_PORT } else { v.trim().parse().ok()? };
+
+        match Broadcaster::bind(port) {
+            Ok(b) => Some(b),
+            Err(e) => {
+                error!(Net, "couldn't broadcast on port {}: {}", port, e);
+                None
+            }
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|a| a.port()).unwrap_or(0)
+    }
+
+    //called once per frame, with None while nobody is playing
+    pub fn send(&mut self, snapshot: Option<&Snapshot>) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                let _ = stream.set_nodelay(true);
+
+                self.clients.push(Client {
+                    stream,
+                    backlog: Vec::new(),
+                });
+            }
+        }
+
+        if self.clients.is_empty() {
+            return;
+        }
+
+        let frame = encode(snapshot);
+        self.retain_clients(|c| c.push(&frame));
+    }
+
+    //writes whatever the sockets take right now
+    pub fn flush(&mut self) {
+        self.retain_clients(Client::flush);
+    }
+
+    fn retain_clients<F: FnMut(&mut Client) -> bool>(&mut self, mut keep: F) {
+        let mut i = 0;
+
+        while i < self.clients.len() {
+            if keep(&mut self.clients[i]) {
+                i += 1;
+            } else {
+                self.clients.swap_remove(i);
+            }
+        }
+    }
+}
+
+pub struct Spectator {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    snapshot: Option<Snapshot>,
+    closed: bool,
+}
+
+impl Spectator {
+    //the address can be given without a port, then the default broadcast port is used
+    pub fn connect(addr: &str) -> io::Result<Spectator> {
+        let addr = with_default_port(addr, DEFAULT_BROADCAST_PORT);
+        let mut error = io::Error::new(ErrorKind::NotFound, format!("Couldn't resolve {}", addr));
+
+        for a in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&a, CONNECT_TIMEOUT) {
+                Ok(stream) => {
+                    stream.set_nonblocking(true)?;
+
+                    return Ok(Spectator {
+                        stream,
+                        buf: Vec::new(),
+                        snapshot: None,
+                        closed: false,
+                    });
+                }
+                Err(e) => error = e,
+            }
+        }
+
+        Err(error)
+    }
+
+    //connects on another thread, so resolving the address and waiting for the
+    //broadcasting game don't hold up the window
+    pub fn connect_in_background(addr: &str) -> Connecting {
+        let (tx, rx) = mpsc::channel();
+        let addr = addr.to_string();
+
+        thread::spawn(move || {
+            let _ = tx.send(Spectator::connect(&addr));
+        });
+
+        Connecting { result: rx }
+    }
+
+    //reads everything that arrived and keeps the newest frame, returns true if there was one
+    pub fn poll(&mut self) -> bool {
+        let mut chunk = [0u8; 4096];
+
+        while !self.closed {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => self.closed = true,
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => self.closed = true,
+            }
+        }
+
+        let mut received = false;
+
+        while self.buf.len() >= 4 {
+            let len = read_u32(&self.buf) as usize;
+
+            if len > MAX_FRAME {
+                self.closed = true;
+                break;
+            }
+
+            if self.buf.len() < 4 + len {
+                break;
+            }
+
+            match decode(&self.buf[4..4 + len]) {
+                Some(s) => {
+                    self.snapshot = s;
+                    received = true;
+                }
+                None => {
+                    self.closed = true;
+                    break;
+                }
+            }
+
+            self.buf.drain(..4 + len);
+        }
+
+        received
+    }
+
+    //None while the broadcasting game sits in a menu
+    pub fn snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot.as_ref()
+    }
+
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+}
+
+pub struct Connecting {
+    result: Receiver<io::Result<Spectator>>,
+}
+
+impl Connecting {
+    //None while the connection is still being made
+    pub fn poll(&mut self) -> Option<io::Result<Spectator>> {
+        match self.result.try_recv() {
+            Ok(r) => Some(r),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(io::Error::new(ErrorKind::Other, "the connection attempt failed"))),
+        }
+    }
+}
+
+//plays a scripted run, broadcasts it over loopback and checks the spectator saw the same thing
+pub fn loopback_test(frames: usize) -> Result<String, String> {
+    let mut broadcaster = Broadcaster::bind(0).map_err(|e| e.to_string())?;
+    let mut spectator = Spectator::connect(&format!("127.0.0.1:{}", broadcaster.port())).map_err(|e| e.to_string())?;
+
+    let start = Vector2f::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0);
+    let mut tower = TowerState::new(start, 0.0);
+    let mut score = 0.0;
+    let mut rng = Rng::new(1);
+    let mut received = 0;
+
+    for _ in 0..frames {
+        let (x, y) = (rng.range(0.0, WIN_WIDTH), rng.range(0.0, WIN_HEIGHT));
+        tower.input(TowerInput::Aim(x, y));
+        if rng.next_f32() < 0.03 {
+            tower.input(TowerInput::Teleport(x, y));
+        }
+
+        tower.update(FIXED_DELTA);
+        score += score_rate(tower.num_bullets()) * FIXED_DELTA;
+
+        if tower.dead {
+            tower = TowerState::new(start, 0.0);
+            score = 0.0;
+        }
+
+        broadcaster.send(Some(&Snapshot { score, tower: tower.clone() }));
+
+        if spectator.poll() {
+            received += 1;
+        }
+    }
+
+    let started = Instant::now();
+
+    loop {
+        broadcaster.flush();
+        spectator.poll();
+
+        let matches = match spectator.snapshot() {
+            Some(s) => s.score == score
+                && s.tower.position == tower.position
+                && s.tower.rotation == tower.rotation
+                && s.tower.bullets.iter().zip(tower.bullets.iter()).all(|(a, b)| a.position == b.position)
+                && s.tower.num_bullets() == tower.num_bullets(),
+            None => false,
+        };
+
+        if matches {
+            break;
+        }
+
+        if spectator.closed() || started.elapsed() > CONNECT_TIMEOUT {
+            return Err("The spectator never caught up with the last frame".to_string());
+        }
+
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    Ok(format!("{} of {} frames arrived in time, the last one matches", received, frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spectators_see_the_last_frame() {
+        let result = loopback_test(600);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+}
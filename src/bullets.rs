@@ -0,0 +1,387 @@
+use sfml::system::Vector2f;
+
+use crate::sim::{Circle, Rules, NEAR_MISS_DISTANCE};
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// The bullets of a tower, stored as one array per field instead of one struct per bullet, so
+// an update walks through memory in a straight line and never recomputes the direction of a
+// bullet that didn't bounce. With the `parallel` feature large stores are updated on all cores.
+// The results are exactly the same either way, every bullet only depends on itself.
+
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 2048; //below this splitting the work costs more than it saves
+
+//a copy of one bullet, handed out when iterating
+#[derive(Clone, Copy)]
+pub struct Bullet {
+    pub position: Vector2f,
+    pub rotation: f32,
+    pub velocity: Vector2f,
+    pub radius: f32,
+}
+
+//what one update found out about the bullets and the tower they were checked against
+pub struct Contact {
+    pub hit: bool,
+    pub closest: f32,
+    pub near_misses: u32,
+}
+
+#[derive(Clone)]
+pub struct BulletStore {
+    x: Vec<f32>,
+    y: Vec<f32>,
+    vx: Vec<f32>,
+    vy: Vec<f32>,
+    rotation: Vec<f32>,
+    speed: Vec<f32>,
+    radius: Vec<f32>,
+    near: Vec<bool>,
+    arena: Vector2f,
+}
+
+impl BulletStore {
+    pub fn new() -> BulletStore {
+        BulletStore {
+            x: Vec::new(),
+            y: Vec::new(),
+            vx: Vec::new(),
+            vy: Vec::new(),
+            rotation: Vec::new(),
+            speed: Vec::new(),
+            radius: Vec::new(),
+            near: Vec::new(),
+            arena: Vector2f::new(WIN_WIDTH, WIN_HEIGHT),
+        }
+    }
+
+    //the edges every bullet in the store bounces off
+    pub fn set_arena(&mut self, arena: Vector2f) {
+        self.arena = arena;
+    }
+
+    //a bullet fired from a position, it starts a little ahead so it doesn't sit inside the tower
+    pub fn fire(&mut self, position: Vector2f, rotation: f32, radius: f32, rules: &Rules) {
+        self.insert(position, rotation, radius, rules.bullet_speed);
+
+        let i = self.len() - 1;
+        let arena = self.arena;
+        step(&mut self.x[i], &mut self.y[i], &mut self.vx[i], &mut self.vy[i], &mut self.rotation[i],
+             self.speed[i], arena, 1.0 / 100.0);
+    }
+
+    //a bullet exactly where it is given, like one that was received over the network
+    pub fn insert(&mut self, position: Vector2f, rotation: f32, radius: f32, speed: f32) {
+        let (vx, vy) = velocity(rotation, speed);
+
+        self.x.push(position.x);
+        self.y.push(position.y);
+        self.vx.push(vx);
+        self.vy.push(vy);
+        self.rotation.push(rotation);
+        self.speed.push(speed);
+        self.radius.push(radius);
+        self.near.push(false);
+    }
+
+    pub fn len(&self) -> usize {
+        self.x.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.vx.clear();
+        self.vy.clear();
+        self.rotation.clear();
+        self.speed.clear();
+        self.radius.clear();
+        self.near.clear();
+    }
+
+    pub fn get(&self, i: usize) -> Option<Bullet> {
+        if i >= self.len() {
+            return None;
+        }
+
+        Some(Bullet {
+            position: Vector2f::new(self.x[i], self.y[i]),
+            rotation: self.rotation[i],
+            velocity: Vector2f::new(self.vx[i], self.vy[i]),
+            radius: self.radius[i],
+        })
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            store: self,
+            i: 0,
+        }
+    }
+
+    //moves every bullet and checks it against the tower that fired them
+    pub fn update(&mut self, d: f32, tower: &Circle) -> Contact {
+        #[cfg(feature = "parallel")]
+        {
+            if self.len() >= PARALLEL_THRESHOLD {
+                return self.update_parallel(d, tower);
+            }
+        }
+
+        self.update_sequential(d, tower)
+    }
+
+    pub fn update_sequential(&mut self, d: f32, tower: &Circle) -> Contact {
+        let mut contact = Contact {
+            hit: false,
+            closest: std::f32::INFINITY,
+            near_misses: 0,
+        };
+        let arena = self.arena;
+
+        for i in 0..self.len() {
+            step(&mut self.x[i], &mut self.y[i], &mut self.vx[i], &mut self.vy[i], &mut self.rotation[i],
+                 self.speed[i], arena, d);
+
+            let (hit, gap, missed) = touch(self.x[i], self.y[i], self.radius[i], &mut self.near[i], tower);
+            contact.hit |= hit;
+            contact.closest = contact.closest.min(gap);
+            contact.near_misses += missed as u32;
+        }
+
+        contact
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn update_parallel(&mut self, d: f32, tower: &Circle) -> Contact {
+        use rayon::prelude::*;
+
+        let arena = self.arena;
+
+        (&mut self.x, &mut self.y, &mut self.vx, &mut self.vy, &mut self.rotation, &mut self.near, &self.speed, &self.radius)
+            .into_par_iter()
+            .map(|(x, y, vx, vy, rotation, near, speed, radius)| {
+                step(x, y, vx, vy, rotation, *speed, arena, d);
+                touch(*x, *y, *radius, near, tower)
+            })
+            .fold(|| Contact { hit: false, closest: std::f32::INFINITY, near_misses: 0 }, |c, (hit, gap, missed)| Contact {
+                hit: c.hit || hit,
+                closest: c.closest.min(gap),
+                near_misses: c.near_misses + missed as u32,
+            })
+            .reduce(|| Contact { hit: false, closest: std::f32::INFINITY, near_misses: 0 }, |a, b| Contact {
+                hit: a.hit || b.hit,
+                closest: a.closest.min(b.closest),
+                near_misses: a.near_misses + b.near_misses,
+            })
+    }
+
+    //whether any of the bullets overlaps a hitbox
+    pub fn hits(&self, hitbox: &Circle) -> bool {
+        (0..self.len()).any(|i| Circle::new(self.x[i], self.y[i], self.radius[i]).is_colliding(hitbox))
+    }
+}
+
+fn velocity(rotation: f32, speed: f32) -> (f32, f32) {
+    let r = rotation.to_radians();
+
+    (r.cos() * speed, r.sin() * speed)
+}
+
+//bounces off the edges of the arena, the direction is only worked out again after a bounce
+fn step(x: &mut f32, y: &mut f32, vx: &mut f32, vy: &mut f32, rotation: &mut f32, speed: f32, arena: Vector2f, d: f32) {
+    let mut bounced = false;
+
+    if *x <= 0.0 || *x >= arena.x {
+        *rotation = -*rotation + 180.0;
+        bounced = true;
+    }
+
+    if *y <= 0.0 || *y >= arena.y {
+        *rotation = -*rotation;
+        bounced = true;
+    }
+
+    if bounced {
+        let v = velocity(*rotation, speed);
+        *vx = v.0;
+        *vy = v.1;
+    }
+
+    *x += *vx * d;
+    *y += *vy * d;
+}
+
+//returns whether the bullet hit the tower, the gap between them and whether it just flew past
+fn touch(x: f32, y: f32, radius: f32, near: &mut bool, tower: &Circle) -> (bool, f32, bool) {
+    let hitbox = Circle::new(x, y, radius);
+    let gap = hitbox.gap(tower);
+    let mut missed = false;
+
+    if gap.max(0.0) < NEAR_MISS_DISTANCE {
+        *near = true;
+    } else if *near {
+        *near = false;
+        missed = true;
+    }
+
+    (gap < 0.0, gap.max(0.0), missed)
+}
+
+pub struct Iter<'a> {
+    store: &'a BulletStore,
+    i: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Bullet;
+
+    fn next(&mut self) -> Option<Bullet> {
+        let b = self.store.get(self.i)?;
+        self.i += 1;
+
+        Some(b)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.store.len() - self.i;
+        (left, Some(left))
+    }
+}
+
+impl<'a> IntoIterator for &'a BulletStore {
+    type Item = Bullet;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Rng;
+
+    //how a bullet was updated before the bullets became a store, the store has to match it exactly
+    struct OldBullet {
+        hitbox: Circle,
+        position: Vector2f,
+        rotation: f32,
+        speed: f32,
+        arena: Vector2f,
+        near: bool,
+    }
+
+    impl OldBullet {
+        fn new(pos: Vector2f, rot: f32, radius: f32, rules: &Rules) -> OldBullet {
+            let mut b = OldBullet {
+                hitbox: Circle::new(pos.x, pos.y, radius),
+                position: pos,
+                rotation: rot,
+                speed: rules.bullet_speed,
+                arena: Vector2f::new(rules.arena_width, rules.arena_height),
+                near: false,
+            };
+
+            b.update(1.0 / 100.0);
+
+            b
+        }
+
+        fn update(&mut self, d: f32) {
+            if self.position.x <= 0.0 || self.position.x >= self.arena.x {
+                self.rotation = -self.rotation + 180.0;
+            }
+
+            if self.position.y <= 0.0 || self.position.y >= self.arena.y {
+                self.rotation = -self.rotation;
+            }
+
+            self.position.x += self.rotation.to_radians().cos() * self.speed * d;
+            self.position.y += self.rotation.to_radians().sin() * self.speed * d;
+
+            self.hitbox.x = self.position.x;
+            self.hitbox.y = self.position.y;
+        }
+    }
+
+    #[test]
+    fn store_matches_the_old_update() {
+        let rules = Rules::default();
+        let tower = Circle::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0, 33.0);
+        let mut rng = Rng::new(7);
+
+        let mut store = BulletStore::new();
+        let mut old = Vec::new();
+
+        for _ in 0..200 {
+            let p = Vector2f::new(rng.range(-5.0, WIN_WIDTH + 5.0), rng.range(-5.0, WIN_HEIGHT + 5.0));
+            let r = rng.range(-720.0, 720.0);
+
+            store.fire(p, r, 16.0, &rules);
+            old.push(OldBullet::new(p, r, 16.0, &rules));
+        }
+
+        for frame in 0..600 {
+            let d = 1.0 / 60.0 + rng.next_f32() * 0.01;
+            let contact = store.update_sequential(d, &tower);
+
+            let (mut hit, mut closest, mut near_misses) = (false, std::f32::INFINITY, 0);
+            for b in &mut old {
+                b.update(d);
+
+                hit |= b.hitbox.is_colliding(&tower);
+                let gap = b.hitbox.gap(&tower).max(0.0);
+                closest = closest.min(gap);
+
+                if gap < NEAR_MISS_DISTANCE {
+                    b.near = true;
+                } else if b.near {
+                    b.near = false;
+                    near_misses += 1;
+                }
+            }
+
+            for (new, old) in store.iter().zip(old.iter()) {
+                assert_eq!(new.position.x.to_bits(), old.position.x.to_bits(), "frame {}", frame);
+                assert_eq!(new.position.y.to_bits(), old.position.y.to_bits(), "frame {}", frame);
+                assert_eq!(new.rotation.to_bits(), old.rotation.to_bits(), "frame {}", frame);
+            }
+
+            assert_eq!(contact.hit, hit, "frame {}", frame);
+            assert_eq!(contact.closest.to_bits(), closest.to_bits(), "frame {}", frame);
+            assert_eq!(contact.near_misses, near_misses, "frame {}", frame);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_matches_sequential() {
+        let rules = Rules::default();
+        let tower = Circle::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0, 33.0);
+        let mut rng = Rng::new(8);
+        let mut store = BulletStore::new();
+
+        for _ in 0..PARALLEL_THRESHOLD * 2 {
+            let p = Vector2f::new(rng.range(0.0, WIN_WIDTH), rng.range(0.0, WIN_HEIGHT));
+            store.fire(p, rng.range(-180.0, 180.0), 16.0, &rules);
+        }
+
+        let mut parallel = store.clone();
+
+        for _ in 0..300 {
+            let a = store.update_sequential(1.0 / 60.0, &tower);
+            let b = parallel.update_parallel(1.0 / 60.0, &tower);
+
+            assert_eq!((a.hit, a.closest.to_bits(), a.near_misses), (b.hit, b.closest.to_bits(), b.near_misses));
+        }
+
+        assert!(store.iter().zip(parallel.iter()).all(|(a, b)| a.position == b.position && a.rotation == b.rotation));
+    }
+}
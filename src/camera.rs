@@ -0,0 +1,159 @@
+use std::env;
+
+use sfml::graphics::View;
+use sfml::system::{SfBox, Vector2f};
+
+use crate::sim::TowerInput;
+use crate::tween::{Easing, Tween};
+use crate::utils::Rng;
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// Decides which part of the arena the window shows. The camera glides after its target,
+// never shows anything outside the arena and can shake and zoom. With an arena as large as
+// the window it stays put, so only the shakes are noticeable.
+// MOVING_TOWER_ARENA=2000x1200 plays on a larger arena, the camera then follows the tower.
+
+const FOLLOW_RATE: f32 = 5.0; //how quickly the camera catches up, per second
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 4.0;
+
+pub fn arena_from_env() -> Option<Vector2f> {
+    let v = env::var("MOVING_TOWER_ARENA").ok()?;
+    let mut parts = v.trim().split('x');
+
+    let w: f32 = parts.next()?.trim().parse().ok()?;
+    let h: f32 = parts.next()?.trim().parse().ok()?;
+
+    if w < WIN_WIDTH || h < WIN_HEIGHT {
+        warn!(Scenes, "the arena can't be smaller than the window ({}x{})", WIN_WIDTH, WIN_HEIGHT);
+        return None;
+    }
+
+    Some(Vector2f::new(w, h))
+}
+
+//the view for things that stay in place on the screen, like the score
+pub fn hud_view() -> SfBox<View> {
+    View::new(Vector2f::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0), Vector2f::new(WIN_WIDTH, WIN_HEIGHT))
+}
+
+pub struct Camera {
+    arena: Vector2f,
+    center: Vector2f,
+    target: Vector2f,
+    zoom: Tween, //1 shows as much as the window is large, 2 twice as much
+    shake: f32, //how far the view jumps around, fades out linearly
+    shake_fade: f32,
+    offset: Vector2f,
+    rng: Rng,
+}
+
+impl Camera {
+    pub fn new(arena: Vector2f) -> Camera {
+        let center = Vector2f::new(arena.x / 2.0, arena.y / 2.0);
+
+        Camera {
+            arena,
+            center,
+            target: center,
+            zoom: Tween::fixed(1.0),
+            shake: 0.0,
+            shake_fade: 0.0,
+            offset: Vector2f::new(0.0, 0.0),
+            rng: Rng::new(1),
+        }
+    }
+
+    pub fn follow(&mut self, target: Vector2f) {
+        self.target = target;
+    }
+
+    //jumps to the target instead of gliding there, for the first frame
+    pub fn snap(&mut self) {
+        self.center = self.target;
+        self.clamp();
+    }
+
+    pub fn zoom_to(&mut self, zoom: f32, duration: f32) {
+        let zoom = zoom.max(MIN_ZOOM).min(MAX_ZOOM);
+        self.zoom.retarget(zoom, duration, Easing::QuadInOut);
+    }
+
+    //the zoom that shows the whole arena
+    pub fn overview_zoom(&self) -> f32 {
+        (self.arena.x / WIN_WIDTH).max(self.arena.y / WIN_HEIGHT).max(1.0)
+    }
+
+    pub fn zoom_target(&self) -> f32 {
+        self.zoom.target()
+    }
+
+    //a stronger shake replaces a weaker one, a weaker one is ignored
+    pub fn shake(&mut self, strength: f32, duration: f32) {
+        if strength > self.shake {
+            self.shake = strength;
+            self.shake_fade = strength / duration.max(0.01);
+        }
+    }
+
+    pub fn update(&mut self, d: f32) {
+        self.zoom.update(d);
+
+        let t = 1.0 - (-FOLLOW_RATE * d).exp();
+        self.center += (self.target - self.center) * t;
+        self.clamp();
+
+        self.shake = (self.shake - self.shake_fade * d).max(0.0);
+        self.offset = Vector2f::new(self.rng.range(-1.0, 1.0), self.rng.range(-1.0, 1.0)) * self.shake;
+    }
+
+    //keeps the view inside the arena, an arena smaller than the view is centered
+    fn clamp(&mut self) {
+        let size = self.size();
+
+        self.center.x = if self.arena.x > size.x {
+            self.center.x.max(size.x / 2.0).min(self.arena.x - size.x / 2.0)
+        } else {
+            self.arena.x / 2.0
+        };
+
+        self.center.y = if self.arena.y > size.y {
+            self.center.y.max(size.y / 2.0).min(self.arena.y - size.y / 2.0)
+        } else {
+            self.arena.y / 2.0
+        };
+    }
+
+    fn size(&self) -> Vector2f {
+        Vector2f::new(WIN_WIDTH, WIN_HEIGHT) * self.zoom.value()
+    }
+
+    pub fn view(&self) -> SfBox<View> {
+        View::new(self.center + self.offset, self.size())
+    }
+
+    //where a point in the window is in the arena, shaking is left out so aiming stays steady
+    pub fn to_world(&self, x: f32, y: f32) -> Vector2f {
+        let zoom = self.zoom.value();
+
+        Vector2f::new(
+            self.center.x + (x - WIN_WIDTH / 2.0) * zoom,
+            self.center.y + (y - WIN_HEIGHT / 2.0) * zoom,
+        )
+    }
+
+    //mouse inputs come in window coordinates, the tower needs them in arena coordinates
+    pub fn input_to_world(&self, input: TowerInput) -> TowerInput {
+        match input {
+            TowerInput::Aim(x, y) => {
+                let p = self.to_world(x, y);
+                TowerInput::Aim(p.x, p.y)
+            }
+            TowerInput::Teleport(x, y) => {
+                let p = self.to_world(x, y);
+                TowerInput::Teleport(p.x, p.y)
+            }
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use std::path::PathBuf;
+
+use crate::assets;
+use crate::balance;
+use crate::bench;
+use crate::bots::{self, Controller};
+use crate::broadcast;
+use crate::gym;
+use crate::net;
+use crate::pack;
+use crate::replay::Replay;
+use crate::scenes;
+use crate::script;
+use crate::sim::Rules;
+use crate::stress;
+use crate::utils::get_path;
+
+// The command line of the game. Options change the window and what the game starts with,
+// subcommands do something else instead, mostly without opening a window. Options can come
+// before or after the subcommand, except for `balance` which takes the rest for itself.
+
+pub const USAGE: &str = "\
+usage: moving-tower [options] [command]
+
+options:
+  --resources <dir>           load the assets from another directory
+  --window <width>x<height>   open the window in another size, the game is scaled
+  --fullscreen                play on the whole screen
+  --seed <n>                  seeds what is random in simulate, bots and a --mode bot or script
+  --mode <name>               start playing right away: classic, versus, a bot or a script
+  --no-vsync                  don't hold the frame rate at 60
+
+commands:
+  play-replay <file>          watch a saved replay
+  simulate [--bot <name>] [--games <n>]
+                              let a bot play runs without a window, greedy and 20 by default
+  validate-assets             check that every asset and script loads
+  pack [dir] [file]           pack a resource directory into one file, by default resources/
+                              next to the executable into resources.pack, where the game finds it
+  reset-highscore             forget the highscore and the best run
+  bots [games]                let every bot play a number of runs without a window
+  balance [options]           sweep the rules with bots, see balance.rs
+  bench [bullets]             time the bullet update
+  stress [bullets]            draw a lot of bullets to see how fast rendering is
+  gym [frame_skip]            serve the training environment over stdin and stdout
+  net-loopback [latency_ms,jitter_ms,loss_percent]
+                              check that two peers stay in sync over loopback
+  broadcast-loopback          stream a run to a spectator over loopback
+  help                        show this";
+
+const DEFAULT_SIMULATE_GAMES: u64 = 20;
+const DEFAULT_BOTS_GAMES: u64 = 20;
+const MAX_RUN_DURATION: f32 = 600.0;
+const SEED_ONLY: &str = "--seed only applies to simulate, bots and a --mode that is a bot or a script";
+
+pub struct Options {
+    pub resources: Option<PathBuf>,
+    pub window: Option<(u32, u32)>,
+    pub fullscreen: bool,
+    pub seed: Option<u64>,
+    pub mode: Option<String>,
+    pub vsync: bool,
+}
+
+pub enum Command {
+    Play,
+    PlayReplay(String),
+    Simulate(String, u64), //the bot and the number of games
+    ValidateAssets,
+    Pack(PathBuf, PathBuf), //the directory and the pack
+    ResetHighscore,
+    Bots(u64),
+    Balance(Vec<String>),
+    Bench(Vec<usize>),
+    Stress(usize),
+    Gym(u32),
+    NetLoopback(net::Conditions),
+    BroadcastLoopback,
+    Help,
+}
+
+//what the window shows first
+pub enum Start {
+    Menu,
+    Stress(usize),
+    Replay(Replay),
+    Classic,
+    Versus,
+    Bot(Box<Controller>),
+    Script(String, u64), //and the seed of its random numbers
+}
+
+//the arguments without the name of the binary
+pub fn parse(args: &[String]) -> Result<(Options, Command), String> {
+    let mut options = Options {
+        resources: None,
+        window: None,
+        fullscreen: false,
+        seed: None,
+        mode: None,
+        vsync: true,
+    };
+    let mut command: Option<String> = None;
+    let mut rest: Vec<String> = Vec::new(); //what is left for the command
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        let value = || args.get(i + 1).ok_or(format!("{} needs a value", arg));
+
+        if command.as_ref().map(|c| c.as_str()) == Some("balance") {
+            rest.push(args[i].clone());
+            i += 1;
+            continue;
+        }
+
+        match arg {
+            "--resources" => options.resources = Some(PathBuf::from(value()?)),
+            "--window" => options.window = Some(parse_size(value()?)?),
+            "--seed" => {
+                let v = value()?;
+                options.seed = Some(v.parse().map_err(|_| format!("Not a seed: {}", v))?);
+            }
+            "--mode" => options.mode = Some(value()?.clone()),
+            "--fullscreen" => options.fullscreen = true,
+            "--no-vsync" => options.vsync = false,
+            "--help" | "-h" => command = Some("help".to_string()),
+            a if command.is_none() && !a.starts_with("--") => command = Some(a.to_string()),
+            a => rest.push(a.to_string()),
+        }
+
+        //options with a value skip it
+        i += match arg {
+            "--resources" | "--window" | "--seed" | "--mode" => 2,
+            _ => 1,
+        };
+    }
+
+    let command = match command {
+        None if !rest.is_empty() => return Err(format!("Unknown option {}", rest[0])),
+        None => Command::Play,
+        Some(c) => parse_command(&c, &rest)?,
+    };
+
+    match command {
+        Command::Play => {}
+        _ if options.mode.is_some() => return Err("--mode only applies when playing".to_string()),
+        Command::Simulate(..) | Command::Bots(_) => {}
+        _ if options.seed.is_some() => return Err(SEED_ONLY.to_string()),
+        _ => {}
+    }
+
+    Ok((options, command))
+}
+
+fn parse_command(name: &str, rest: &[String]) -> Result<Command, String> {
+    match name {
+        "simulate" => return parse_simulate(rest),
+        "balance" => return Ok(Command::Balance(rest.to_vec())),
+        _ => {}
+    }
+
+    //the others take one argument at most
+    let takes = match name {
+        "validate-assets" | "reset-highscore" | "broadcast-loopback" | "help" => 0,
+        "pack" => 2,
+        _ => 1,
+    };
+    if let Some(a) = rest.get(takes) {
+        return Err(format!("{} doesn't take {}", name, a));
+    }
+
+    let number = |what: &str| -> Result<Option<u64>, String> {
+        match rest.get(0) {
+            Some(n) => n.parse().map(Some).map_err(|_| format!("Not a number of {}: {}", what, n)),
+            None => Ok(None),
+        }
+    };
+
+    let command = match name {
+        "play-replay" => Command::PlayReplay(rest.get(0).ok_or("play-replay needs a file")?.clone()),
+        "validate-assets" => Command::ValidateAssets,
+        "pack" => Command::Pack(
+            rest.get(0).map_or_else(|| PathBuf::from(get_path("resources")), PathBuf::from),
+            rest.get(1).map_or_else(|| PathBuf::from(get_path(&format!("resources.{}", pack::EXTENSION))), PathBuf::from),
+        ),
+        "reset-highscore" => Command::ResetHighscore,
+        "bots" => Command::Bots(number("games")?.unwrap_or(DEFAULT_BOTS_GAMES)),
+        "bench" => match number("bullets")? {
+            Some(b) => Command::Bench(vec![b as usize]),
+            None => Command::Bench(bench::DEFAULT_BENCH_BULLETS.to_vec()),
+        },
+        "stress" => Command::Stress(number("bullets")?.map_or(stress::DEFAULT_STRESS_BULLETS, |b| b as usize)),
+        "gym" => Command::Gym(number("frames to skip")?.map_or(1, |f| f as u32)),
+        "net-loopback" => match rest.get(0) {
+            Some(c) => Command::NetLoopback(net::Conditions::parse(c).ok_or(format!("Not latency_ms,jitter_ms,loss_percent: {}", c))?),
+            None => Command::NetLoopback(net::Conditions::default()),
+        },
+        "broadcast-loopback" => Command::BroadcastLoopback,
+        "help" => Command::Help,
+        c => return Err(format!("Unknown command {}", c)),
+    };
+
+    Ok(command)
+}
+
+fn parse_simulate(rest: &[String]) -> Result<Command, String> {
+    let mut bot = "greedy".to_string();
+    let mut games = DEFAULT_SIMULATE_GAMES;
+
+    let mut i = 0;
+    while i < rest.len() {
+        let value = rest.get(i + 1).ok_or(format!("{} needs a value", rest[i]))?;
+
+        match rest[i].as_str() {
+            "--bot" => bot = value.clone(),
+            "--games" => games = value.parse().map_err(|_| format!("Not a number of games: {}", value))?,
+            o => return Err(format!("Unknown option {}", o)),
+        }
+
+        i += 2;
+    }
+
+    if bots::by_name(&bot, 0).is_none() {
+        return Err(format!("Unknown bot {}, there are: {}", bot, bots::BOT_NAMES.join(", ")));
+    }
+
+    Ok(Command::Simulate(bot, games))
+}
+
+//`1280x720`
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let mut parts = s.trim().split('x');
+    let error = || format!("Not a window size like 1280x720: {}", s);
+
+    let w: u32 = parts.next().and_then(|w| w.trim().parse().ok()).ok_or_else(error)?;
+    let h: u32 = parts.next().and_then(|h| h.trim().parse().ok()).ok_or_else(error)?;
+
+    if parts.next().is_some() || w == 0 || h == 0 {
+        return Err(error());
+    }
+
+    Ok((w, h))
+}
+
+//runs the commands that don't need a window, None for the ones that do
+pub fn run(command: &Command, options: &Options) -> Option<Result<String, String>> {
+    let result = match command {
+        Command::Play | Command::PlayReplay(_) | Command::Stress(_) => return None,
+        Command::Help => Ok(USAGE.to_string()),
+        Command::Simulate(bot, games) => simulate(bot, *games, options.seed.unwrap_or(0)),
+        Command::ValidateAssets => assets::validate(),
+        Command::Pack(dir, file) => pack::build(dir, file),
+        Command::ResetHighscore => scenes::reset_highscore(),
+        Command::Bots(games) => Ok(all_bots(*games, options.seed.unwrap_or(0))),
+        Command::Balance(args) => balance::Sweep::from_args(args).and_then(balance::run),
+        Command::Bench(counts) => bench::run(counts),
+        Command::Gym(frame_skip) => gym::serve(*frame_skip).map(|_| String::new()).map_err(|e| e.to_string()),
+        Command::NetLoopback(conditions) => net::loopback_test(*conditions, 1800),
+        Command::BroadcastLoopback => broadcast::loopback_test(1800),
+    };
+
+    Some(result)
+}
+
+//the games are seeded one after another from the seed, seed 0 starts in the middle like the game
+fn simulate(bot: &str, games: u64, seed: u64) -> Result<String, String> {
+    let mut lines = Vec::new();
+    let (mut total, mut best, mut worst) = (0.0, 0.0f32, std::f32::INFINITY);
+
+    for g in 0..games {
+        let seed = seed.wrapping_add(g);
+        let mut b = bots::by_name(bot, seed).ok_or(format!("Unknown bot {}", bot))?;
+        let run = bots::play_headless(&mut *b, Rules::default(), seed, MAX_RUN_DURATION);
+
+        lines.push(format!("seed {:>6}: score {:>8.1}, {:>6.1}s, {} teleports", seed, run.score, run.duration, run.teleports));
+        total += run.score;
+        best = best.max(run.score);
+        worst = worst.min(run.score);
+    }
+
+    if games > 0 {
+        lines.push(format!("{}: average score {:.1}, best {:.1}, worst {:.1} over {} runs",
+                           bot, total / games as f32, best, worst, games));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+fn all_bots(games: u64, seed: u64) -> String {
+    let mut lines = Vec::new();
+
+    for name in bots::BOT_NAMES.iter() {
+        let (mut score, mut time) = (0.0, 0.0);
+
+        //seeded like simulate, greedy and lookahead only vary with the seed of the game
+        for g in 0..games {
+            let seed = seed.wrapping_add(g);
+
+            if let Some(mut bot) = bots::by_name(name, seed) {
+                let run = bots::play_headless(&mut *bot, Rules::default(), seed, MAX_RUN_DURATION);
+                score += run.score;
+                time += run.duration;
+            }
+        }
+
+        lines.push(format!("{:>10}: average score {:.1}, average time {:.1}s over {} runs",
+                           name, score / games as f32, time / games as f32, games));
+    }
+
+    lines.join("\n")
+}
+
+//the scene the window starts with, after the resources were set so the scripts can be found
+pub fn start(command: Command, options: &Options) -> Result<Start, String> {
+    let mode = match (command, &options.mode) {
+        (Command::Stress(bullets), _) => return Ok(Start::Stress(bullets)),
+        (Command::PlayReplay(path), _) => return Replay::load(&path).map(Start::Replay),
+        (_, None) if options.seed.is_some() => return Err(SEED_ONLY.to_string()),
+        (_, None) => return Ok(Start::Menu),
+        (_, Some(mode)) => mode,
+    };
+    let seed = options.seed.unwrap_or(0);
+
+    match mode.as_str() {
+        "classic" | "versus" if options.seed.is_some() => Err(SEED_ONLY.to_string()),
+        "classic" => Ok(Start::Classic),
+        "versus" => Ok(Start::Versus),
+        m => match bots::by_name(m, seed) {
+            Some(bot) => Ok(Start::Bot(bot)),
+            None if script::available().iter().any(|s| s == m) => Ok(Start::Script(m.to_string(), seed)),
+            None => Err(format!("Unknown mode {}, there are: classic, versus, {}, {}",
+                                m, bots::BOT_NAMES.join(", "), script::available().join(", "))),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn options_go_before_or_after_the_command() {
+        for a in &["--seed 5 --no-vsync bots 10", "bots --seed 5 10 --no-vsync", "bots 10 --no-vsync --seed 5"] {
+            let (o, c) = parse(&args(a)).unwrap();
+
+            assert_eq!(o.seed, Some(5));
+            assert!(!o.vsync);
+            assert!(matches!(c, Command::Bots(10)));
+        }
+
+        let (o, c) = parse(&args("--window 1280x720 --resources mods play-replay run.replay --fullscreen")).unwrap();
+        assert_eq!(o.window, Some((1280, 720)));
+        assert_eq!(o.resources, Some(PathBuf::from("mods")));
+        assert!(o.fullscreen);
+        assert!(matches!(c, Command::PlayReplay(ref f) if f == "run.replay"));
+    }
+
+    #[test]
+    fn balance_takes_the_rest() {
+        let (o, c) = parse(&args("--no-vsync balance --games 5 --seed 3 --window 1")).unwrap();
+
+        assert!(!o.vsync);
+        assert_eq!((o.seed, o.window), (None, None));
+        assert!(matches!(c, Command::Balance(ref a) if *a == args("--games 5 --seed 3 --window 1")));
+    }
+
+    #[test]
+    fn mode_only_applies_when_playing() {
+        let (o, c) = parse(&args("--mode classic")).unwrap();
+        assert_eq!(o.mode.as_deref(), Some("classic"));
+        assert!(matches!(c, Command::Play));
+
+        assert!(parse(&args("--mode classic bots")).is_err());
+        assert!(parse(&args("bench --mode greedy")).is_err());
+        assert!(parse(&args("balance --mode greedy")).is_ok()); //an argument of balance
+    }
+
+    #[test]
+    fn seed_only_applies_where_it_changes_something() {
+        assert!(parse(&args("--seed 3 simulate --bot random")).is_ok());
+        assert!(parse(&args("--seed 3 bench")).is_err());
+        assert!(parse(&args("stress --seed 3")).is_err());
+
+        let (o, c) = parse(&args("--seed 3 --mode classic")).unwrap();
+        assert!(start(c, &o).is_err());
+
+        let (o, c) = parse(&args("--seed 3")).unwrap();
+        assert!(start(c, &o).is_err());
+
+        let (o, c) = parse(&args("--seed 3 --mode greedy")).unwrap();
+        assert!(matches!(start(c, &o), Ok(Start::Bot(_))));
+    }
+
+    #[test]
+    fn commands_and_their_arguments() {
+        assert!(matches!(parse(&args("")).unwrap().1, Command::Play));
+        assert!(matches!(parse(&args("--help")).unwrap().1, Command::Help));
+        assert!(matches!(parse(&args("bots")).unwrap().1, Command::Bots(DEFAULT_BOTS_GAMES)));
+        assert!(matches!(parse(&args("gym 4")).unwrap().1, Command::Gym(4)));
+        assert!(matches!(parse(&args("bench 500")).unwrap().1, Command::Bench(ref b) if *b == vec![500]));
+        assert!(matches!(parse(&args("simulate --games 3 --bot lookahead")).unwrap().1,
+                         Command::Simulate(ref b, 3) if b == "lookahead"));
+        assert!(matches!(parse(&args("pack mods out.pack")).unwrap().1,
+                         Command::Pack(ref d, ref f) if *d == PathBuf::from("mods") && *f == PathBuf::from("out.pack")));
+
+        assert!(parse(&args("fly")).is_err());
+        assert!(parse(&args("--fly")).is_err());
+        assert!(parse(&args("--seed")).is_err());
+        assert!(parse(&args("--seed x bots")).is_err());
+        assert!(parse(&args("bots many")).is_err());
+        assert!(parse(&args("bots 1 2")).is_err());
+        assert!(parse(&args("reset-highscore now")).is_err());
+        assert!(parse(&args("play-replay")).is_err());
+        assert!(parse(&args("simulate --bot nobody")).is_err());
+        assert!(parse(&args("simulate --games")).is_err());
+    }
+
+    #[test]
+    fn window_sizes() {
+        assert_eq!(parse_size("1280x720"), Ok((1280, 720)));
+        assert!(parse_size("1280").is_err());
+        assert!(parse_size("0x720").is_err());
+        assert!(parse_size("1280x720x2").is_err());
+        assert!(parse_size("wide").is_err());
+    }
+
+    #[test]
+    fn bots_play_other_games_with_other_seeds() {
+        let greedy = |seed| all_bots(2, seed).lines().find(|l| l.contains("greedy")).unwrap().to_string();
+        assert_ne!(greedy(0), greedy(7));
+
+        //the seeds of the games wrap around
+        assert!(simulate("random", 2, std::u64::MAX).is_ok());
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+
+use sfml::graphics::*;
+use sfml::system::Vector2f;
+use sfml::window::{Event, Key};
+
+use crate::assets::AssetManager;
+use crate::camera;
+use crate::theme::Theme;
+use crate::tween::{Easing, Tween};
+
+use super::WIN_WIDTH;
+
+// The key below escape (~ or `) drops down a console to type commands into. The scene that is
+// shown decides which commands there are, see `Commands`. Up and down go through the lines
+// entered before, tab completes the command or, after it, the first argument.
+
+const HEIGHT: f32 = 220.0;
+const SLIDE_DURATION: f32 = 0.15;
+const LOG_LINES: usize = 8;
+const HISTORY: usize = 50;
+
+pub type Handler<T> = fn(&mut T, &[&str]) -> Result<String, String>;
+
+struct Command<T> {
+    name: &'static str,
+    usage: &'static str,
+    arguments: Vec<String>, //what tab offers for the first argument
+    handler: Handler<T>,
+}
+
+//the commands a scene understands, `T` is the scene they change
+pub struct Commands<T> {
+    commands: Vec<Command<T>>,
+}
+
+impl<T> Commands<T> {
+    pub fn new() -> Commands<T> {
+        Commands {
+            commands: Vec::new(),
+        }
+    }
+
+    //the usage is shown when the command is called wrong and by `help`
+    pub fn register(&mut self, name: &'static str, usage: &'static str, handler: Handler<T>) -> &mut Commands<T> {
+        self.commands.push(Command {
+            name,
+            usage,
+            arguments: Vec::new(),
+            handler,
+        });
+
+        self
+    }
+
+    //lets tab complete the first argument of the command registered last
+    pub fn arguments<S: ToString>(&mut self, arguments: &[S]) -> &mut Commands<T> {
+        if let Some(c) = self.commands.last_mut() {
+            c.arguments = arguments.iter().map(|a| a.to_string()).collect();
+        }
+
+        self
+    }
+
+    pub fn run(&self, target: &mut T, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((n, a)) => (*n, a),
+            None => return Ok(String::new()),
+        };
+
+        if name == "help" {
+            return Ok(self.commands.iter().map(|c| c.usage).collect::<Vec<_>>().join("\n"));
+        }
+
+        match self.commands.iter().find(|c| c.name == name) {
+            Some(c) => (c.handler)(target, args).map_err(|e| format!("{}\nusage: {}", e, c.usage)),
+            None => Err(format!("Unknown command `{}`, `help` lists them", name)),
+        }
+    }
+
+    //the line with the word that is being typed completed as far as it is unambiguous
+    pub fn complete(&self, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let typing = !line.ends_with(' ');
+
+        let (done, partial, options): (&[&str], &str, Vec<&str>) = match (words.len(), typing) {
+            (0, _) => (&[], "", Vec::new()),
+            (1, true) => (&[], words[0], self.commands.iter().map(|c| c.name).chain(Some("help")).collect()),
+            (1, false) | (2, true) => {
+                let options = match self.commands.iter().find(|c| c.name == words[0]) {
+                    Some(c) => c.arguments.iter().map(|a| a.as_str()).collect(),
+                    None => Vec::new(),
+                };
+
+                (&words[..1], if typing { words[1] } else { "" }, options)
+            }
+            _ => return line.to_string(),
+        };
+
+        let matches: Vec<&str> = options.into_iter().filter(|o| o.starts_with(partial)).collect();
+        let completed = match matches.split_first() {
+            Some((first, rest)) => {
+                let mut common = first.to_string();
+                for m in rest {
+                    while !m.starts_with(common.as_str()) {
+                        common.pop();
+                    }
+                }
+
+                if rest.is_empty() { common + " " } else { common }
+            }
+            None => return line.to_string(),
+        };
+
+        done.iter().map(|w| format!("{} ", w)).collect::<String>() + &completed
+    }
+}
+
+pub struct Console<'a> {
+    open: bool,
+    slide: Tween, //0 is hidden, 1 all the way down
+    input: String,
+    history: VecDeque<String>,
+    browsing: Option<usize>,
+    log: VecDeque<String>,
+    background: RectangleShape<'a>,
+    text: Text<'a>,
+}
+
+impl<'a> Console<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme) -> Console<'a> {
+        let background = {
+            let mut r = RectangleShape::with_size(Vector2f::new(WIN_WIDTH, HEIGHT));
+            let b = theme.background;
+            r.set_fill_color(&Color::rgba(b.r, b.g, b.b, 230));
+            r.set_outline_color(&theme.text);
+            r.set_outline_thickness(1.0);
+
+            r
+        };
+
+        let text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.hud_size / 2);
+            t.set_fill_color(&theme.text);
+
+            t
+        };
+
+        Console {
+            open: false,
+            slide: Tween::fixed(0.0),
+            input: String::new(),
+            history: VecDeque::new(),
+            browsing: None,
+            log: VecDeque::new(),
+            background,
+            text,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.slide.retarget(if self.open { 1.0 } else { 0.0 }, SLIDE_DURATION, Easing::QuadInOut);
+    }
+
+    pub fn print(&mut self, s: &str) {
+        for line in s.lines() {
+            if self.log.len() == LOG_LINES {
+                self.log.pop_front();
+            }
+            self.log.push_back(line.to_string());
+        }
+    }
+
+    //returns a line once it is entered, `complete` is called when tab is pressed
+    pub fn event<F: Fn(&str) -> String>(&mut self, evt: Event, complete: F) -> Option<String> {
+        match evt {
+            Event::KeyPressed { code: Key::Up, .. } => {
+                let i = self.browsing.map_or(self.history.len(), |i| i).checked_sub(1)?;
+                self.browse(Some(i));
+            }
+            Event::KeyPressed { code: Key::Down, .. } => {
+                let i = self.browsing? + 1;
+                self.browse(if i < self.history.len() { Some(i) } else { None });
+            }
+            Event::KeyPressed { code: Key::Tab, .. } => {
+                self.input = complete(&self.input);
+            }
+            Event::TextEntered { unicode } => match unicode {
+                '\u{8}' => {
+                    self.input.pop();
+                }
+                '\r' | '\n' => return self.enter(),
+                '`' | '~' => {} //opens and closes the console
+                c if !c.is_control() => self.input.push(c),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        None
+    }
+
+    fn browse(&mut self, i: Option<usize>) {
+        self.browsing = i;
+        self.input = i.and_then(|i| self.history.get(i)).cloned().unwrap_or_default();
+    }
+
+    fn enter(&mut self) -> Option<String> {
+        let line = self.input.trim().to_string();
+        self.input.clear();
+        self.browsing = None;
+
+        if line.is_empty() {
+            return None;
+        }
+
+        if self.history.back() != Some(&line) {
+            if self.history.len() == HISTORY {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+
+        self.print(&format!("> {}", line));
+
+        Some(line)
+    }
+
+    pub fn update(&mut self, d: f32) {
+        self.slide.update(d);
+    }
+
+    pub fn draw(&self, win: &mut RenderWindow) {
+        let slide = self.slide.value();
+        if slide <= 0.0 {
+            return;
+        }
+
+        win.set_view(&camera::hud_view());
+
+        let top = HEIGHT * (slide - 1.0);
+        let mut background = self.background.clone();
+        background.set_position(Vector2f::new(0.0, top));
+        win.draw(&background);
+
+        let mut lines: Vec<&str> = self.log.iter().map(|l| l.as_str()).collect();
+        let prompt = format!("> {}_", self.input);
+        lines.push(&prompt);
+
+        let mut text = self.text.clone();
+        text.set_string(lines.join("\n").as_str());
+        text.set_position(Vector2f::new(8.0, top + HEIGHT - 8.0 - text.local_bounds().height - text.local_bounds().top));
+        win.draw(&text);
+    }
+}
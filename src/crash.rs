@@ -0,0 +1,160 @@
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::panic;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sfml::window::Event;
+
+use crate::logging::{self, Level, Target};
+use crate::replay::Replay;
+use crate::utils::data_dir;
+
+// When the game panics a crash report is written to the data directory: the panic with a
+// backtrace, the version, the settings, the scene and the last input events. The replay of the
+// run that was going on is saved next to it. The next launch starts with a screen that offers
+// to show the report or save a copy somewhere easier to find, after that it isn't offered again.
+// Only the thread the game runs on writes reports, the headless commands and the network
+// threads leave panics to the default hook.
+
+const REPORT: &str = "crash-report.txt";
+const REPLAY: &str = "crash.replay";
+const SEEN_SUFFIX: &str = ".seen"; //reports that were offered once are kept with this appended
+const KEPT_INPUTS: usize = 50;
+
+struct Context {
+    scene: &'static str,
+    inputs: VecDeque<String>,
+}
+
+static CONTEXT: Mutex<Context> = Mutex::new(Context {
+    scene: "start",
+    inputs: VecDeque::new(),
+});
+
+//called on the thread that runs the window
+pub fn install() {
+    let default = panic::take_hook();
+    let game = thread::current().id();
+
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().id() == game {
+            write_report(&info.to_string());
+        }
+        default(info);
+    }));
+}
+
+pub fn set_scene(scene: &'static str) {
+    if let Ok(mut c) = CONTEXT.lock() {
+        c.scene = scene;
+    }
+}
+
+pub fn record_input(evt: &Event) {
+    if let Ok(mut c) = CONTEXT.lock() {
+        if c.inputs.len() == KEPT_INPUTS {
+            c.inputs.pop_front();
+        }
+        c.inputs.push_back(format!("{:?}", evt));
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn write_report(info: &str) {
+    let mut r = String::new();
+
+    r += &format!("Moving Tower {} crashed ({} {})\n", env!("CARGO_PKG_VERSION"), env::consts::OS, env::consts::ARCH);
+    r += &format!("unix time: {}\n", unix_time());
+
+    //the lock is only taken for moments that can't panic, but better no report than a hang
+    let (scene, inputs) = match CONTEXT.try_lock() {
+        Ok(c) => (c.scene, c.inputs.iter().cloned().collect()),
+        Err(_) => ("unknown", Vec::new()),
+    };
+    r += &format!("scene: {}\n", scene);
+    r += &format!("panic: {}\n", info);
+
+    r += "\nsettings:\n";
+    r += &format!("  arguments: {}\n", env::args().skip(1).collect::<Vec<_>>().join(" "));
+    for (k, v) in env::vars().filter(|(k, _)| k.starts_with("MOVING_TOWER")) {
+        r += &format!("  {}={}\n", k, v);
+    }
+
+    r += "\nlast input events, oldest first:\n";
+    for i in inputs {
+        r += &format!("  {}\n", i);
+    }
+
+    r += &format!("\nbacktrace:\n{}\n", Backtrace::force_capture());
+
+    let dir = data_dir();
+    let path = dir.join(REPORT);
+    let _ = fs::remove_file(dir.join(REPLAY)); //an older replay doesn't belong to this crash
+
+    //not with error!, the panic may have happened while the logger was locked
+    match fs::create_dir_all(&dir).and_then(|_| fs::write(&path, r)) {
+        Ok(_) => logging::try_write(Level::Error, Target::Scenes, format_args!("crashed in the {} scene: {}, the report is in {}", scene, info, path.display())),
+        Err(e) => logging::try_write(Level::Error, Target::Scenes, format_args!("crashed in the {} scene: {}, couldn't write the report: {}", scene, info, e)),
+    }
+}
+
+//called after the panic was caught, while the run it happened in is still there, a replay
+//that isn't complete is kept anyway as it still shows most of the run
+pub fn save_replay(replay: &Replay, complete: bool) {
+    let dir = data_dir();
+    let path = dir.join(REPLAY);
+
+    let mut line = match replay.save(&path.to_string_lossy()) {
+        Ok(_) => format!("\nreplay of the run: {}\n", path.display()),
+        Err(e) => format!("\ncouldn't save the replay of the run: {}\n", e),
+    };
+
+    if !complete {
+        line += "the replay is incomplete, the developer console changed the run and commands aren't recorded\n";
+    }
+
+    if let Ok(mut f) = OpenOptions::new().append(true).open(dir.join(REPORT)) {
+        let _ = f.write_all(line.as_bytes());
+    }
+}
+
+//the report of a crash that wasn't offered yet
+pub fn pending() -> Option<String> {
+    fs::read_to_string(data_dir().join(REPORT)).ok()
+}
+
+//so the next launch doesn't offer it again
+pub fn dismiss() {
+    let dir = data_dir();
+
+    for name in &[REPORT, REPLAY] {
+        let _ = fs::rename(dir.join(name), dir.join(format!("{}{}", name, SEEN_SUFFIX)));
+    }
+}
+
+//copies the report and the replay to the home directory, returns where the report went
+pub fn save_copy() -> Result<PathBuf, String> {
+    let dir = data_dir();
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let name = format!("moving-tower-crash-{}", unix_time());
+    let report = home.join(format!("{}.txt", name));
+
+    fs::copy(dir.join(REPORT), &report).map_err(|e| e.to_string())?;
+
+    if dir.join(REPLAY).exists() {
+        fs::copy(dir.join(REPLAY), home.join(format!("{}.replay", name))).map_err(|e| e.to_string())?;
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+
+use sfml::graphics::*;
+use sfml::system::Vector2f;
+
+use crate::assets::AssetManager;
+use crate::camera;
+use crate::sim::{Circle, TowerState};
+use crate::theme::Theme;
+
+use super::WIN_WIDTH;
+
+// F3 shows the debug overlay on top of any scene: the hitboxes of every tower and bullet the
+// scene has, the frame rate with a graph of the last frame times and the number of bullets.
+// While it is shown a right click picks the tower or bullet under the mouse, its values are
+// then shown and kept up to date until something else or nothing is picked.
+
+const GRAPH_FRAMES: usize = 120;
+const GRAPH_SCALE: f32 = 3.0; //pixels per millisecond
+const GRAPH_HEIGHT: f32 = 100.0;
+const CIRCLE_SEGMENTS: usize = 16;
+const PICK_SLACK: f32 = 4.0; //bullets are small and fast, so they can be picked a little outside
+
+const TOWER_COLOR: Color = Color { r: 0, g: 200, b: 0, a: 255 };
+const BULLET_COLOR: Color = Color { r: 230, g: 60, b: 60, a: 255 };
+const SELECTED_COLOR: Color = Color { r: 250, g: 200, b: 0, a: 255 };
+
+#[derive(Clone, Copy, PartialEq)]
+enum Selection {
+    Tower(usize),
+    Bullet(usize, usize), //the tower and the bullet's place among its bullets
+}
+
+pub struct DebugOverlay<'a> {
+    visible: bool,
+    frames: VecDeque<f32>,
+    selection: Option<Selection>,
+    info_text: Text<'a>,
+    inspect_text: Text<'a>,
+}
+
+impl<'a> DebugOverlay<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme) -> DebugOverlay<'a> {
+        let text = |y: f32| {
+            let mut t = Text::new("", am.get_font(theme.font), theme.hud_size / 2);
+            t.set_fill_color(&theme.text);
+            t.set_outline_color(&theme.background);
+            t.set_outline_thickness(1.0);
+            t.set_position(Vector2f::new(WIN_WIDTH - 250.0, y));
+
+            t
+        };
+
+        DebugOverlay {
+            visible: false,
+            frames: VecDeque::with_capacity(GRAPH_FRAMES),
+            selection: None,
+            info_text: text(5.0),
+            inspect_text: text(GRAPH_HEIGHT + 60.0),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.selection = None;
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    //frame times are kept even while hidden, so the graph is full as soon as it is shown
+    pub fn update(&mut self, d: f32, towers: &[&TowerState]) {
+        if self.frames.len() == GRAPH_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(d);
+
+        if !self.visible {
+            return;
+        }
+
+        let avg = self.frames.iter().sum::<f32>() / self.frames.len() as f32;
+        let worst = self.frames.iter().cloned().fold(0.0, f32::max);
+        let bullets: usize = towers.iter().map(|t| t.num_bullets()).sum();
+
+        let s = format!("{:.0} fps, {:.1} ms, worst {:.1} ms\n{} bullets", 1.0 / avg, avg * 1000.0, worst * 1000.0, bullets);
+        self.info_text.set_string(s.as_str());
+
+        //the picked bullet is gone once the bullets were cleared
+        let s = match self.selection.and_then(|s| describe(s, towers)) {
+            Some(s) => s,
+            None => {
+                self.selection = None;
+                "right click to inspect".to_string()
+            }
+        };
+        self.inspect_text.set_string(s.as_str());
+    }
+
+    //picks whatever is closest to a point in the arena, or nothing if the point is empty
+    pub fn pick(&mut self, point: Vector2f, towers: &[&TowerState]) {
+        let mut best: Option<(f32, Selection)> = None;
+        let mut consider = |hitbox: Circle, slack: f32, s: Selection| {
+            let gap = hitbox.gap(&Circle::new(point.x, point.y, 0.0));
+
+            if gap <= slack && best.map_or(true, |(g, _)| gap < g) {
+                best = Some((gap, s));
+            }
+        };
+
+        for (t, tower) in towers.iter().enumerate() {
+            consider(tower.hitbox, 0.0, Selection::Tower(t));
+
+            for (b, bullet) in tower.bullets.iter().enumerate() {
+                consider(bullet_hitbox(bullet.position, bullet.radius), PICK_SLACK, Selection::Bullet(t, b));
+            }
+        }
+
+        self.selection = best.map(|(_, s)| s);
+    }
+
+    pub fn draw(&self, win: &mut RenderWindow, view: &View, towers: &[&TowerState]) {
+        if !self.visible {
+            return;
+        }
+
+        let mut shapes = VertexArray::new(PrimitiveType::Lines, 0);
+
+        for (t, tower) in towers.iter().enumerate() {
+            for (b, bullet) in tower.bullets.iter().enumerate() {
+                let color = if self.selection == Some(Selection::Bullet(t, b)) { SELECTED_COLOR } else { BULLET_COLOR };
+                add_circle(&mut shapes, bullet_hitbox(bullet.position, bullet.radius), color);
+            }
+
+            let color = if self.selection == Some(Selection::Tower(t)) { SELECTED_COLOR } else { TOWER_COLOR };
+            add_circle(&mut shapes, tower.hitbox, color);
+        }
+
+        win.set_view(view);
+        win.draw(&shapes);
+
+        win.set_view(&camera::hud_view());
+        win.draw(&self.graph());
+        win.draw(&self.info_text);
+        win.draw(&self.inspect_text);
+    }
+
+    //one bar per frame, with lines at 60 and 30 frames per second
+    fn graph(&self) -> VertexArray {
+        let mut v = VertexArray::new(PrimitiveType::Lines, 0);
+        let left = WIN_WIDTH - 250.0;
+        let bottom = GRAPH_HEIGHT + 50.0;
+
+        for &ms in &[1000.0 / 60.0, 1000.0 / 30.0] {
+            let y = bottom - ms * GRAPH_SCALE;
+            v.append(&Vertex::with_pos_color(Vector2f::new(left, y), Color::rgba(128, 128, 128, 160)));
+            v.append(&Vertex::with_pos_color(Vector2f::new(left + GRAPH_FRAMES as f32 * 2.0, y), Color::rgba(128, 128, 128, 160)));
+        }
+
+        for (i, d) in self.frames.iter().enumerate() {
+            let x = left + i as f32 * 2.0;
+            let ms = d * 1000.0;
+            let color = if ms > 1000.0 / 55.0 { BULLET_COLOR } else { TOWER_COLOR };
+
+            v.append(&Vertex::with_pos_color(Vector2f::new(x, bottom), color));
+            v.append(&Vertex::with_pos_color(Vector2f::new(x, bottom - (ms * GRAPH_SCALE).min(GRAPH_HEIGHT)), color));
+        }
+
+        v
+    }
+}
+
+fn bullet_hitbox(position: Vector2f, radius: f32) -> Circle {
+    Circle::new(position.x, position.y, radius)
+}
+
+fn add_circle(v: &mut VertexArray, c: Circle, color: Color) {
+    let point = |i: usize| {
+        let a = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::PI * 2.0;
+        Vector2f::new(c.x + a.cos() * c.radius, c.y + a.sin() * c.radius)
+    };
+
+    for i in 0..CIRCLE_SEGMENTS {
+        v.append(&Vertex::with_pos_color(point(i), color));
+        v.append(&Vertex::with_pos_color(point(i + 1), color));
+    }
+}
+
+fn describe(s: Selection, towers: &[&TowerState]) -> Option<String> {
+    match s {
+        Selection::Tower(t) => {
+            let tower = towers.get(t)?;
+
+            Some(format!("tower {}\nposition {:.1} {:.1}\nrotation {:.1}\nhitbox radius {:.1}\nbullets {}\nteleports {}",
+                         t, tower.position.x, tower.position.y, tower.rotation, tower.hitbox.radius,
+                         tower.num_bullets(), tower.teleports()))
+        }
+        Selection::Bullet(t, b) => {
+            let bullet = towers.get(t)?.bullets.get(b)?;
+            let speed = f32::sqrt(bullet.velocity.x.powi(2) + bullet.velocity.y.powi(2));
+
+            Some(format!("bullet {} of tower {}\nposition {:.1} {:.1}\nrotation {:.1}\nvelocity {:.1} {:.1} ({:.1}/s)\nhitbox radius {:.1}",
+                         b, t, bullet.position.x, bullet.position.y, bullet.rotation,
+                         bullet.velocity.x, bullet.velocity.y, speed, bullet.radius))
+        }
+    }
+}
+
@@ -0,0 +1,105 @@
+use std::env;
+use std::path::Path;
+
+use sfml::system::Vector2f;
+
+use crate::replay::Replay;
+use crate::scenes::score_rate;
+use crate::sim::TowerState;
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// Plays a recorded run next to the current one, frame time by frame time, so the player can
+// race their best run. The best run is kept in best.replay, MOVING_TOWER_GHOST can point
+// to any other replay file.
+
+pub const BEST_REPLAY: &str = "best.replay";
+
+pub struct Ghost {
+    replay: Replay,
+    state: TowerState,
+    frame: usize,
+    time: f32,
+    score: f32,
+    finished: bool,
+}
+
+impl Ghost {
+    pub fn new(replay: Replay) -> Ghost {
+        Ghost {
+            replay,
+            state: TowerState::new(Vector2f::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0), 0.0),
+            frame: 0,
+            time: 0.0,
+            score: 0.0,
+            finished: false,
+        }
+    }
+
+    //None if there is no run to race yet
+    pub fn load() -> Option<Ghost> {
+        let path = env::var("MOVING_TOWER_GHOST").unwrap_or_else(|_| BEST_REPLAY.to_string());
+
+        if !Path::new(&path).exists() {
+            return None;
+        }
+
+        match Replay::load(&path) {
+            //the ghost plays the normal game, a game mode or another arena would show another run
+            Ok(Replay { script: Some(ref name), .. }) => {
+                warn!(Save, "{} is a run of the game mode {}, it can't be raced", path, name);
+                None
+            }
+            Ok(Replay { arena: Some((w, h)), .. }) => {
+                warn!(Save, "{} was played on a {}x{} arena, it can't be raced", path, w, h);
+                None
+            }
+            Ok(r) => Some(Ghost::new(r)),
+            Err(e) => {
+                warn!(Save, "couldn't load the ghost: {}", e);
+                None
+            }
+        }
+    }
+
+    //simulates the recorded run until it has been going for as long as the current one
+    pub fn advance_to(&mut self, time: f32) {
+        while !self.finished && self.time < time {
+            let f = match self.replay.frame(self.frame) {
+                Some(f) => f,
+                None => {
+                    self.finished = true;
+                    break;
+                }
+            };
+
+            self.state.update(f.delta);
+            self.time += f.delta;
+            self.score += score_rate(self.state.num_bullets()) * f.delta;
+
+            if self.state.dead {
+                self.finished = true;
+                break;
+            }
+
+            for i in &f.inputs {
+                self.state.input(*i);
+            }
+
+            self.frame += 1;
+        }
+    }
+
+    pub fn state(&self) -> &TowerState {
+        &self.state
+    }
+
+    //once the ghost run is over this is its final score
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
@@ -0,0 +1,261 @@
+use std::io::{self, BufRead, Write};
+
+use sfml::system::Vector2f;
+
+use crate::bots::Decision;
+use crate::net::FIXED_DELTA;
+use crate::sim::{Rules, TowerState};
+use crate::stats::RunStats;
+use crate::utils::Rng;
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// A gym-like environment around the game rules for training agents: reset with a seed,
+// then step with an action and get back what the tower sees, the points scored during
+// the step and whether the run is over. Nothing is drawn, so it runs as fast as the cpu can.
+
+pub const MAX_DURATION: f32 = 600.0; //episodes are cut off after ten minutes of game time by default, whatever the frame skip
+pub const OBSERVED_BULLETS: usize = 32;
+
+pub struct Observation {
+    pub position: (f32, f32),
+    pub rotation: f32,
+    pub bullets: Vec<(f32, f32, f32)>, //position and heading in degrees, nearest first
+    pub bullet_speed: f32,
+}
+
+impl Observation {
+    fn new(tower: &TowerState) -> Observation {
+        let p = tower.position;
+        let mut bullets: Vec<(f32, f32, f32)> = tower.bullets.iter()
+            .map(|b| (b.position.x, b.position.y, b.rotation))
+            .collect();
+
+        let dist = |b: &(f32, f32, f32)| (b.0 - p.x).powi(2) + (b.1 - p.y).powi(2);
+        bullets.sort_by(|a, b| dist(a).partial_cmp(&dist(b)).unwrap_or(std::cmp::Ordering::Equal));
+
+        Observation {
+            position: (p.x, p.y),
+            rotation: tower.rotation,
+            bullets,
+            bullet_speed: tower.rules().bullet_speed,
+        }
+    }
+
+    //a fixed size vector for networks: the tower followed by the nearest bullets relative to it,
+    //each as (present, dx, dy, heading cos, heading sin) with positions scaled by the window width
+    pub fn to_vec(&self) -> Vec<f32> {
+        let (x, y) = self.position;
+        let r = self.rotation.to_radians();
+        let mut v = vec![x / WIN_WIDTH, y / WIN_HEIGHT, r.cos(), r.sin()];
+
+        for i in 0..OBSERVED_BULLETS {
+            match self.bullets.get(i) {
+                Some(&(bx, by, br)) => {
+                    let br = br.to_radians();
+                    v.extend_from_slice(&[1.0, (bx - x) / WIN_WIDTH, (by - y) / WIN_WIDTH, br.cos(), br.sin()]);
+                }
+                None => v.extend_from_slice(&[0.0; 5]),
+            }
+        }
+
+        v
+    }
+
+    fn to_json(&self) -> String {
+        let bullets: Vec<String> = self.bullets.iter()
+            .map(|(x, y, r)| format!("[{},{},{}]", x, y, r))
+            .collect();
+
+        let features: Vec<String> = self.to_vec().iter().map(|f| f.to_string()).collect();
+
+        format!("{{\"position\":[{},{}],\"rotation\":{},\"bullets\":[{}],\"bullet_speed\":{},\"features\":[{}]}}",
+                self.position.0, self.position.1, self.rotation, bullets.join(","), self.bullet_speed, features.join(","))
+    }
+}
+
+pub struct Env {
+    rules: Rules,
+    tower: TowerState,
+    stats: RunStats,
+    score: f32,
+    frame_skip: u32,
+    max_duration: f32,
+}
+
+impl Env {
+    pub fn new() -> Env {
+        Env::with_frame_skip(1)
+    }
+
+    //every step repeats the action for this many frames, the teleport only happens in the first
+    pub fn with_frame_skip(frame_skip: u32) -> Env {
+        let mut e = Env {
+            rules: Rules::default(),
+            tower: TowerState::new(Vector2f::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0), 0.0),
+            stats: RunStats::new(),
+            score: 0.0,
+            frame_skip: frame_skip.max(1),
+            max_duration: MAX_DURATION,
+        };
+
+        e.reset(0);
+
+        e
+    }
+
+    //restarts the episode with other rules
+    pub fn with_rules(mut self, rules: Rules) -> Env {
+        self.rules = rules;
+        self.reset(0);
+        self
+    }
+
+    //episodes that survive this long are cut off
+    pub fn with_max_duration(mut self, seconds: f32) -> Env {
+        self.max_duration = seconds;
+        self
+    }
+
+    //seed 0 starts in the middle like the game does, other seeds start somewhere random
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        let (position, rotation) = if seed == 0 {
+            (Vector2f::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0), 0.0)
+        } else {
+            let mut rng = Rng::new(seed);
+            let x = rng.range(WIN_WIDTH / 4.0, WIN_WIDTH * 3.0 / 4.0);
+            let y = rng.range(WIN_HEIGHT / 4.0, WIN_HEIGHT * 3.0 / 4.0);
+
+            (Vector2f::new(x, y), rng.range(-180.0, 180.0))
+        };
+
+        self.tower = TowerState::new(position, rotation).with_rules(self.rules);
+        self.stats = RunStats::new();
+        self.score = 0.0;
+
+        Observation::new(&self.tower)
+    }
+
+    //the reward is the score the step earned, so the total reward of an episode is its score
+    pub fn step(&mut self, action: Decision) -> (Observation, f32, bool) {
+        let before = self.score;
+        let mut action = action;
+
+        for _ in 0..self.frame_skip {
+            if self.done() {
+                break;
+            }
+
+            for i in action.inputs() {
+                self.tower.input(i);
+            }
+            action.teleport = false;
+
+            self.tower.update(FIXED_DELTA);
+            self.score += self.rules.score_rate(self.tower.num_bullets()) * FIXED_DELTA;
+            self.stats.update(FIXED_DELTA, self.score, &self.tower);
+        }
+
+        (Observation::new(&self.tower), self.score - before, self.done())
+    }
+
+    pub fn done(&self) -> bool {
+        self.tower.dead || self.stats.duration >= self.max_duration
+    }
+
+    pub fn tower(&self) -> &TowerState {
+        &self.tower
+    }
+
+    pub fn stats(&self) -> &RunStats {
+        &self.stats
+    }
+}
+
+// `moving-tower gym [frame_skip]` drives an Env over stdin and stdout, one command per line:
+//   reset <seed>               -> {"observation": ...}
+//   step <x> <y> <teleport>    -> {"observation": ..., "reward": r, "done": true|false}
+// teleport is 0 or 1, errors are answered with {"error": "..."}.
+pub fn serve(frame_skip: u32) -> io::Result<()> {
+    let mut env = Env::with_frame_skip(frame_skip);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["quit"] => break,
+            [] => continue,
+            _ => {}
+        }
+
+        writeln!(out, "{}", answer(&mut env, &line))?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+//the reply to a command of `serve` other than quit
+fn answer(env: &mut Env, line: &str) -> String {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    match parts.as_slice() {
+        ["reset"] => format!("{{\"observation\":{}}}", env.reset(0).to_json()),
+        ["reset", seed] => match seed.parse() {
+            Ok(s) => format!("{{\"observation\":{}}}", env.reset(s).to_json()),
+            Err(_) => "{\"error\":\"the seed has to be a number\"}".to_string(),
+        },
+        ["step", x, y, teleport] => match (x.parse::<f32>(), y.parse::<f32>()) {
+            //NaN would stay in the tower's position and isn't valid JSON
+            (Ok(x), Ok(y)) if x.is_finite() && y.is_finite() => {
+                let (o, r, d) = env.step(Decision {
+                    target: (x, y),
+                    teleport: *teleport == "1",
+                });
+
+                format!("{{\"observation\":{},\"reward\":{},\"done\":{}}}", o.to_json(), r, d)
+            }
+            _ => "{\"error\":\"x and y have to be finite numbers\"}".to_string(),
+        },
+
+        _ => format!("{{\"error\":\"unknown command: {}\"}}", escape(line)),
+    }
+}
+
+//the line as the inside of a JSON string
+fn escape(s: &str) -> String {
+    let mut e = String::new();
+
+    for c in s.chars() {
+        match c {
+            '"' => e.push_str("\\\""),
+            '\\' => e.push_str("\\\\"),
+            c if (c as u32) < 0x20 => e.push_str(&format!("\\u{:04x}", c as u32)),
+            c => e.push(c),
+        }
+    }
+
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_broken_commands_with_errors() {
+        let mut env = Env::new();
+        answer(&mut env, "reset 3");
+
+        for line in &["step nan 1 0", "step 1 inf 1", "step -inf 2 0", "step 1 x 0"] {
+            assert_eq!(answer(&mut env, line), "{\"error\":\"x and y have to be finite numbers\"}");
+        }
+        assert!(env.tower().position.x.is_finite());
+
+        assert_eq!(answer(&mut env, "jump \\\"up\"\t"), "{\"error\":\"unknown command: jump \\\\\\\"up\\\"\\u0009\"}");
+        assert!(answer(&mut env, "step 10 20 1").starts_with("{\"observation\":"));
+    }
+}
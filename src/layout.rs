@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+
+use sfml::graphics::Color;
+
+use crate::scenes::State;
+use crate::theme::FontSize;
+
+// Screens are described in a small ini-like format:
+//
+//   # comment
+//   [text]
+//   string = Highscore: {highscore}
+//   position = center 200
+//
+//   [button]
+//   text = PLAY
+//   bounds = center 300 400 70
+//   action = game
+//
+// `center` can be used instead of an x coordinate, `\n` inside a value is a line break
+// and `{name}` placeholders are filled in by the scene that builds the screen.
+// Fonts, colors and sizes fall back to the current theme when they are left out,
+// sizes can also name a theme size (`title`, `body`, `button`, `hud`).
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Game,
+    Bot,
+    Modes,
+    Menu,
+    Theme,
+    Replay,
+    Stats,
+    Versus,
+    Online,
+    HostVersus,
+    HostCoop,
+    Join,
+    Watch,
+    ShowReport,
+    SaveReport,
+    Exit,
+}
+
+impl Action {
+    fn parse(s: &str) -> Option<Action> {
+        match s {
+            "game" => Some(Action::Game),
+            "bot" => Some(Action::Bot),
+            "modes" => Some(Action::Modes),
+            "menu" => Some(Action::Menu),
+            "theme" => Some(Action::Theme),
+            "replay" => Some(Action::Replay),
+            "stats" => Some(Action::Stats),
+            "versus" => Some(Action::Versus),
+            "online" => Some(Action::Online),
+            "host_versus" => Some(Action::HostVersus),
+            "host_coop" => Some(Action::HostCoop),
+            "join" => Some(Action::Join),
+            "watch" => Some(Action::Watch),
+            "show_report" => Some(Action::ShowReport),
+            "save_report" => Some(Action::SaveReport),
+            "exit" => Some(Action::Exit),
+            _ => None,
+        }
+    }
+
+    //actions that need data from the scene, like the run to replay, are handled by the scene itself
+    pub fn state(&self) -> Option<State> {
+        match self {
+            Action::Game => Some(State::Game),
+            Action::Bot => Some(State::Bot),
+            Action::Modes => Some(State::Modes),
+            Action::Menu => Some(State::Menu(None)),
+            Action::Theme => Some(State::NextTheme),
+            Action::Replay => None,
+            Action::Stats => Some(State::Stats),
+            Action::Versus => Some(State::Versus),
+            Action::Online => Some(State::Lobby),
+            Action::HostVersus | Action::HostCoop | Action::Join | Action::Watch => None,
+            Action::ShowReport | Action::SaveReport => None,
+            Action::Exit => Some(State::Exit),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Coord {
+    Center,
+    Value(f32),
+}
+
+pub struct TextDef {
+    pub string: String,
+    pub font: Option<String>,
+    pub char_size: FontSize,
+    pub color: Option<Color>,
+    pub x: Coord,
+    pub y: f32,
+}
+
+pub struct ButtonDef {
+    pub text: String,
+    pub font: Option<String>,
+    pub char_size: FontSize,
+    pub x: Coord,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub color: Option<Color>,
+    pub border_color: Option<Color>,
+    pub border_thickness: Option<f32>,
+    pub text_color: Option<Color>,
+    pub action: Action,
+}
+
+pub struct ScreenLayout {
+    pub texts: Vec<TextDef>,
+    pub buttons: Vec<ButtonDef>,
+}
+
+impl ScreenLayout {
+    pub fn parse(src: &str) -> Result<ScreenLayout, String> {
+        let mut layout = ScreenLayout {
+            texts: Vec::new(),
+            buttons: Vec::new(),
+        };
+
+        for s in parse_sections(src)? {
+            layout.add(s)?;
+        }
+
+        Ok(layout)
+    }
+
+    fn add(&mut self, s: Section) -> Result<(), String> {
+        match s.kind.as_str() {
+            "text" => {
+                let (x, y) = s.position("position")?;
+
+                self.texts.push(TextDef {
+                    string: s.string("string", ""),
+                    font: s.optional("font"),
+                    char_size: s.font_size("size", FontSize::Body)?,
+                    color: s.color("color")?,
+                    x,
+                    y,
+                });
+            }
+            "button" => {
+                let (x, y, width, height) = s.bounds("bounds")?;
+                let action = {
+                    let a = s.required("action")?;
+                    match Action::parse(a) {
+                        Some(a) => a,
+                        None => {
+                            return Err(format!("line {}: unknown action `{}`", s.line, a));
+                        }
+                    }
+                };
+
+                self.buttons.push(ButtonDef {
+                    text: s.string("text", ""),
+                    font: s.optional("font"),
+                    char_size: s.font_size("size", FontSize::Button)?,
+                    x,
+                    y,
+                    width,
+                    height,
+                    color: s.color("color")?,
+                    border_color: s.color("border_color")?,
+                    border_thickness: match s.optional("border_thickness") {
+                        Some(v) => Some(s.parse_number("border_thickness", &v)?),
+                        None => None,
+                    },
+                    text_color: s.color("text_color")?,
+                    action,
+                });
+            }
+            _ => {
+                return Err(format!("line {}: unknown widget `{}`", s.line, s.kind));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//splits a file in this format into its `[kind]` sections, other files like animations use it too
+pub fn parse_sections(src: &str) -> Result<Vec<Section>, String> {
+    let mut sections = Vec::new();
+    let mut section: Option<Section> = None;
+
+    for (i, line) in src.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            if let Some(s) = section.take() {
+                sections.push(s);
+            }
+
+            section = Some(Section {
+                kind: line[1..line.len() - 1].trim().to_string(),
+                line: i + 1,
+                props: HashMap::new(),
+            });
+        } else if let Some(eq) = line.find('=') {
+            match section {
+                Some(ref mut s) => {
+                    let key = line[..eq].trim().to_string();
+                    let value = line[eq + 1..].trim().replace("\\n", "\n");
+                    s.props.insert(key, value);
+                }
+                None => {
+                    return Err(format!("line {}: property outside of a section", i + 1));
+                }
+            }
+        } else {
+            return Err(format!("line {}: expected `[section]` or `key = value`", i + 1));
+        }
+    }
+
+    if let Some(s) = section.take() {
+        sections.push(s);
+    }
+
+    Ok(sections)
+}
+
+pub struct Section {
+    pub kind: String,
+    pub line: usize,
+    props: HashMap<String, String>,
+}
+
+impl Section {
+    pub fn required(&self, key: &str) -> Result<&str, String> {
+        match self.props.get(key) {
+            Some(v) => Ok(v.as_str()),
+            None => Err(format!("line {}: [{}] is missing `{}`", self.line, self.kind, key)),
+        }
+    }
+
+    pub fn string(&self, key: &str, default: &str) -> String {
+        match self.props.get(key) {
+            Some(v) => v.clone(),
+            None => default.to_string(),
+        }
+    }
+
+    pub fn optional(&self, key: &str) -> Option<String> {
+        self.props.get(key).cloned()
+    }
+
+    fn font_size(&self, key: &str, default: FontSize) -> Result<FontSize, String> {
+        match self.props.get(key).map(|v| v.as_str()) {
+            Some("title") => Ok(FontSize::Title),
+            Some("body") => Ok(FontSize::Body),
+            Some("button") => Ok(FontSize::Button),
+            Some("hud") => Ok(FontSize::Hud),
+            Some(v) => Ok(FontSize::Points(self.parse_number(key, v)? as u32)),
+            None => Ok(default),
+        }
+    }
+
+    fn color(&self, key: &str) -> Result<Option<Color>, String> {
+        match self.props.get(key) {
+            Some(v) => match parse_color(v) {
+                Some(c) => Ok(Some(c)),
+                None => Err(format!("line {}: `{}` is not a color", self.line, v)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn position(&self, key: &str) -> Result<(Coord, f32), String> {
+        let v: Vec<&str> = self.required(key)?.split_whitespace().collect();
+
+        if v.len() != 2 {
+            return Err(format!("line {}: `{}` needs an x and a y value", self.line, key));
+        }
+
+        Ok((self.parse_coord(key, v[0])?, self.parse_number(key, v[1])?))
+    }
+
+    fn bounds(&self, key: &str) -> Result<(Coord, f32, f32, f32), String> {
+        let v: Vec<&str> = self.required(key)?.split_whitespace().collect();
+
+        if v.len() != 4 {
+            return Err(format!("line {}: `{}` needs x, y, width and height", self.line, key));
+        }
+
+        Ok((
+            self.parse_coord(key, v[0])?,
+            self.parse_number(key, v[1])?,
+            self.parse_number(key, v[2])?,
+            self.parse_number(key, v[3])?,
+        ))
+    }
+
+    fn parse_coord(&self, key: &str, v: &str) -> Result<Coord, String> {
+        if v == "center" {
+            Ok(Coord::Center)
+        } else {
+            Ok(Coord::Value(self.parse_number(key, v)?))
+        }
+    }
+
+    pub fn parse_number(&self, key: &str, v: &str) -> Result<f32, String> {
+        match v.parse::<f32>() {
+            Ok(n) => Ok(n),
+            Err(_) => Err(format!("line {}: `{}` expects a number, got `{}`", self.line, key, v)),
+        }
+    }
+}
+
+pub fn parse_color(s: &str) -> Option<Color> {
+    match s {
+        "white" => return Some(Color::WHITE),
+        "black" => return Some(Color::BLACK),
+        "transparent" => return Some(Color::TRANSPARENT),
+        _ => {}
+    }
+
+    let hex = s.trim_start_matches('#');
+
+    match (hex.len(), u32::from_str_radix(hex, 16)) {
+        (6, Ok(c)) => Some(Color::from(c << 8 | 0xFF)),
+        (8, Ok(c)) => Some(Color::from(c)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_sections() {
+        let src = "
+# a comment
+[text]
+string = one\\ntwo
+position = center 200
+
+  [ button ]
+text=PLAY
+bounds = 10 300 400 70
+";
+        let sections = parse_sections(src).unwrap();
+
+        assert_eq!(sections.len(), 2);
+        assert_eq!((sections[0].kind.as_str(), sections[0].line), ("text", 3));
+        assert_eq!(sections[0].required("string"), Ok("one\ntwo"));
+        assert_eq!((sections[1].kind.as_str(), sections[1].line), ("button", 7));
+        assert_eq!(sections[1].required("text"), Ok("PLAY"));
+        assert_eq!(sections[1].string("action", "menu"), "menu");
+        assert_eq!(sections[1].optional("font"), None);
+        assert!(sections[1].required("action").is_err());
+    }
+
+    #[test]
+    fn rejects_broken_sections() {
+        assert!(parse_sections("string = outside").is_err());
+        assert!(parse_sections("[text]\nno value here").is_err());
+        assert_eq!(parse_sections("").map(|s| s.len()), Ok(0));
+
+        let s = &parse_sections("[text]\nsize = big").unwrap()[0];
+        assert!(s.parse_number("size", "big").is_err());
+        assert_eq!(s.parse_number("size", "12.5"), Ok(12.5));
+    }
+
+    #[test]
+    fn parses_screens() {
+        let layout = ScreenLayout::parse("
+[text]
+string = Highscore: {highscore}
+position = center 200
+color = #ff000080
+
+[button]
+text = PLAY
+bounds = center 300 400 70
+action = game
+border_thickness = 2
+").unwrap();
+
+        assert_eq!(layout.texts[0].string, "Highscore: {highscore}");
+        assert!(matches!(layout.texts[0].x, Coord::Center));
+        assert_eq!(layout.texts[0].color, Some(Color::rgba(255, 0, 0, 128)));
+        assert!(layout.buttons[0].action == Action::Game);
+        assert!(matches!(layout.buttons[0].x, Coord::Center));
+        assert_eq!((layout.buttons[0].y, layout.buttons[0].width, layout.buttons[0].height), (300.0, 400.0, 70.0));
+        assert_eq!(layout.buttons[0].border_thickness, Some(2.0));
+
+        assert!(ScreenLayout::parse("[button]\nbounds = 0 0 10 10\naction = fly").is_err());
+        assert!(ScreenLayout::parse("[button]\nbounds = 0 0 10\naction = game").is_err());
+        assert!(ScreenLayout::parse("[text]\nposition = 0 0\ncolor = reddish").is_err());
+        assert!(ScreenLayout::parse("[picture]\nposition = 0 0").is_err());
+    }
+
+    #[test]
+    fn shipped_screens_parse() {
+        for src in &[
+            include_str!("../Assets/menu.screen"),
+            include_str!("../Assets/gameover.screen"),
+            include_str!("../Assets/stats.screen"),
+            include_str!("../Assets/lobby.screen"),
+            include_str!("../Assets/modes.screen"),
+            include_str!("../Assets/crash.screen"),
+        ] {
+            assert!(ScreenLayout::parse(src).is_ok());
+        }
+    }
+}
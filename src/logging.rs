@@ -0,0 +1,264 @@
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::utils::data_dir;
+
+// Everything worth knowing about a session goes through the macros below, for example
+// `info!(Assets, "loaded {} in {:.1} ms", name, ms)`. Lines are appended to moving-tower.log
+// in the data directory, which is rotated once it gets large, and to stderr as well with
+// MOVING_TOWER_LOG_STDERR=1.
+//
+// MOVING_TOWER_LOG sets how much is logged, like `debug` or `info,physics=trace,input=off`.
+// Without it everything from info up is logged.
+
+macro_rules! log_at {
+    ($level:ident, $target:ident, $($arg:tt)+) => {
+        if $crate::logging::enabled($crate::logging::Level::$level, $crate::logging::Target::$target) {
+            $crate::logging::write($crate::logging::Level::$level, $crate::logging::Target::$target, format_args!($($arg)+));
+        }
+    };
+}
+
+macro_rules! error {
+    ($target:ident, $($arg:tt)+) => { log_at!(Error, $target, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($target:ident, $($arg:tt)+) => { log_at!(Warn, $target, $($arg)+) };
+}
+
+macro_rules! info {
+    ($target:ident, $($arg:tt)+) => { log_at!(Info, $target, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($target:ident, $($arg:tt)+) => { log_at!(Debug, $target, $($arg)+) };
+}
+
+macro_rules! trace {
+    ($target:ident, $($arg:tt)+) => { log_at!(Trace, $target, $($arg)+) };
+}
+
+const LOG_FILE: &str = "moving-tower.log";
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const KEPT_LOGS: usize = 3; //the current one and two rotated ones
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Target {
+    Assets,
+    Scenes,
+    Input,
+    Save,
+    Physics,
+    Net,
+    Script,
+}
+
+const TARGETS: [Target; 7] = [Target::Assets, Target::Scenes, Target::Input, Target::Save, Target::Physics, Target::Net, Target::Script];
+
+impl Target {
+    fn name(self) -> &'static str {
+        match self {
+            Target::Assets => "assets",
+            Target::Scenes => "scenes",
+            Target::Input => "input",
+            Target::Save => "save",
+            Target::Physics => "physics",
+            Target::Net => "net",
+            Target::Script => "script",
+        }
+    }
+}
+
+struct Logger {
+    levels: [Level; 7], //indexed like TARGETS
+    file: Option<File>,
+    path: PathBuf,
+    written: u64,
+    stderr: bool,
+    start: Instant,
+}
+
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+//reads the settings from the environment and opens the log file, logging before is dropped
+pub fn init() {
+    let (levels, problems) = parse_levels(&env::var("MOVING_TOWER_LOG").unwrap_or_default());
+    let stderr = env::var("MOVING_TOWER_LOG_STDERR").map_or(false, |v| v == "1");
+
+    let dir = data_dir();
+    let path = dir.join(LOG_FILE);
+    let file = fs::create_dir_all(&dir).ok().and_then(|_| {
+        rotate(&path);
+        OpenOptions::new().create(true).append(true).open(&path).ok()
+    });
+
+    if file.is_none() {
+        eprintln!("Couldn't open the log file {}", path.display());
+    }
+
+    let written = file.as_ref().and_then(|f| f.metadata().ok()).map_or(0, |m| m.len());
+
+    if let Ok(mut l) = LOGGER.lock() {
+        *l = Some(Logger {
+            levels,
+            file,
+            path,
+            written,
+            stderr,
+            start: Instant::now(),
+        });
+    }
+
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    info!(Scenes, "moving-tower {} started, unix time {}", env!("CARGO_PKG_VERSION"), since_epoch);
+
+    for p in problems {
+        warn!(Scenes, "MOVING_TOWER_LOG: {}", p);
+    }
+}
+
+//`info,physics=trace` sets every target to info and then physics to trace
+fn parse_levels(spec: &str) -> ([Level; 7], Vec<String>) {
+    let mut levels = [Level::Info; 7];
+    let mut problems = Vec::new();
+
+    for part in spec.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let (target, level) = match part.find('=') {
+            Some(i) => (Some(&part[..i]), &part[i + 1..]),
+            None => (None, part),
+        };
+
+        let level = match Level::from_name(level) {
+            Some(l) => l,
+            None => {
+                problems.push(format!("unknown level `{}`", level));
+                continue;
+            }
+        };
+
+        match target {
+            None => levels = [level; 7],
+            Some(t) => match TARGETS.iter().position(|x| x.name() == t) {
+                Some(i) => levels[i] = level,
+                None => problems.push(format!("unknown target `{}`", t)),
+            },
+        }
+    }
+
+    (levels, problems)
+}
+
+//moving-tower.log becomes moving-tower.log.1 and so on, the oldest one is dropped
+fn rotate(path: &Path) {
+    if fs::metadata(path).map_or(true, |m| m.len() < MAX_LOG_SIZE) {
+        return;
+    }
+
+    let numbered = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+
+    for n in (1..KEPT_LOGS).rev() {
+        let from = if n == 1 { path.to_path_buf() } else { numbered(n - 1) };
+        let _ = fs::rename(from, numbered(n));
+    }
+}
+
+pub fn enabled(level: Level, target: Target) -> bool {
+    match LOGGER.lock() {
+        Ok(l) => l.as_ref().map_or(false, |l| l.enabled(level, target)),
+        Err(_) => false,
+    }
+}
+
+pub fn write(level: Level, target: Target, args: fmt::Arguments) {
+    if let Ok(mut guard) = LOGGER.lock() {
+        if let Some(ref mut l) = *guard {
+            l.write(level, target, args);
+        }
+    }
+}
+
+//for the panic hook, the panic may have happened while this thread was writing a line, then
+//the line is dropped instead of waiting for a lock that is never given back
+pub fn try_write(level: Level, target: Target, args: fmt::Arguments) {
+    if let Ok(mut guard) = LOGGER.try_lock() {
+        if let Some(ref mut l) = *guard {
+            if l.enabled(level, target) {
+                l.write(level, target, args);
+            }
+        }
+    }
+}
+
+impl Logger {
+    fn enabled(&self, level: Level, target: Target) -> bool {
+        let i = TARGETS.iter().position(|t| *t == target).unwrap_or(0);
+        level != Level::Off && level <= self.levels[i]
+    }
+
+    fn write(&mut self, level: Level, target: Target, args: fmt::Arguments) {
+        let line = format!("{:>10.3} {:<5} {:<7} {}\n", self.start.elapsed().as_secs_f64(), level.name(), target.name(), args);
+
+        if self.stderr {
+            eprint!("{}", line);
+        }
+
+        if self.written >= MAX_LOG_SIZE {
+            self.file = None;
+            rotate(&self.path);
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path).ok();
+            self.written = 0;
+        }
+
+        if let Some(ref mut f) = self.file {
+            if f.write_all(line.as_bytes()).is_ok() {
+                self.written += line.len() as u64;
+            }
+        }
+    }
+}
+
+//milliseconds since a point in time, for timings in log lines
+pub fn ms(since: Instant) -> f64 {
+    since.elapsed().as_secs_f64() * 1000.0
+}
@@ -3,95 +3,349 @@
 extern crate sfml;
 
 use std::boxed::Box;
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
 
 use sfml::graphics::*;
 use sfml::system::*;
 use sfml::window::*;
 
 use crate::assets::AssetManager;
+use crate::broadcast::Broadcaster;
 use crate::scenes::*;
-use crate::utils::get_path;
+use crate::online::{LobbyScene, NetGameScene};
+use crate::theme::Theme;
+use crate::versus::VersusScene;
+use crate::cli::Start;
 
+#[macro_use]
+mod logging;
 mod utils;
 mod scenes;
 mod ui;
+mod theme;
+mod tween;
+mod layout;
 mod assets;
 mod actors;
+mod sim;
+mod replay;
+mod stats;
+mod versus;
+mod net;
+mod online;
+mod broadcast;
+mod bots;
+mod gym;
+mod balance;
+mod ghost;
+mod script;
+mod camera;
+mod animation;
+mod stress;
+mod bullets;
+mod bench;
+mod debug;
+mod console;
+mod crash;
+mod cli;
+mod resources;
+mod pack;
 
 const WIN_SIZE: (u32, u32) = (1000, 600);
 const WIN_WIDTH: f32 = WIN_SIZE.0 as f32;
 const WIN_HEIGHT: f32 = WIN_SIZE.1 as f32;
 
 fn main() {
+    //see cli.rs for the options and commands
+    let args: Vec<String> = env::args().collect();
+    attach_console();
+    logging::init();
+
+    let (options, command) = match cli::parse(&args[1..]) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(ref dir) = options.resources {
+        resources::set_override(dir);
+    }
+
+    if let Some(result) = cli::run(&command, &options) {
+        report(result);
+        return;
+    }
+
+    //only crashes of the game are offered on the next launch, not those of commands
+    crash::install();
+
+    //checked before the window opens, a replay that doesn't load or a damaged pack that was asked for shouldn't flash one
+    let start = match resources::check_requested().and_then(|_| cli::start(command, &options)) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let mut settings = ContextSettings::default();
     settings.antialiasing_level = 4;
 
-    let mut window = RenderWindow::new(
-        VideoMode::new(WIN_SIZE.0, WIN_SIZE.1, 8),
-        "Moving Tower",
-        Style::CLOSE,
-        &settings,
-    );
-
-    window.set_framerate_limit(60);
-
-    let icon = {
-        let path = get_path("resources/icon.png");
-        if let Some(t) = Image::from_file(path.as_str()) {
-            t
-        } else {
-            panic!("Couldn't load icon: {}", path);
-        }
+    let (mode, style) = if options.fullscreen {
+        (VideoMode::desktop_mode(), Style::FULLSCREEN)
+    } else {
+        let (w, h) = options.window.unwrap_or(WIN_SIZE);
+        (VideoMode::new(w, h, 8), Style::CLOSE)
     };
 
-    window.set_icon(icon.size().x, icon.size().y, icon.pixel_data());
+    let mut window = RenderWindow::new(mode, "Moving Tower", style, &settings);
 
-    let mut asset_manager = AssetManager::new("resources/");
-    asset_manager.load_font("font.ttf");
-    asset_manager.load_texture("tower.png");
-    asset_manager.load_texture("bullet.png");
+    //scenes are laid out for WIN_SIZE, other sizes stretch it
+    window.set_view(&camera::hud_view());
+
+    if let Some(icon) = assets::load_icon() {
+        window.set_icon(icon.size().x, icon.size().y, icon.pixel_data());
+    }
+
+    let mut asset_manager = AssetManager::new();
+    asset_manager.load_all();
+
+    let themes = Theme::all();
+    let mut theme = 0;
+
+    let mut broadcaster = Broadcaster::from_env();
+    let mut overlay = debug::DebugOverlay::new(&asset_manager, &themes[theme]);
+    let mut console = console::Console::new(&asset_manager, &themes[theme]);
 
     let mut clock = Clock::default();
-    let mut curscene: Box<Scene> = Box::new(MenuScene::new(&asset_manager, None)) as Box<Scene>;
+    //after a crash the report is offered first, unless the command line asked for something else
+    let (mut curscene, mut scene): (Box<Scene>, &str) = match start {
+        Start::Menu => match crash::pending() {
+            Some(report) => (Box::new(CrashScene::new(&asset_manager, &themes[theme], &report)), "crash"),
+            None => (Box::new(MenuScene::new(&asset_manager, &themes[theme], None)), "menu"),
+        },
+        Start::Stress(bullets) => (Box::new(stress::StressScene::new(&asset_manager, &themes[theme], bullets)), "stress"),
+        Start::Replay(replay) => (Box::new(GameScene::replay(&asset_manager, &themes[theme], replay)), "replay"),
+        Start::Classic => (Box::new(GameScene::new(&asset_manager, &themes[theme])), "game"),
+        Start::Versus => (Box::new(VersusScene::new(&asset_manager, &themes[theme])), "versus"),
+        Start::Bot(bot) => (Box::new(GameScene::bot(&asset_manager, &themes[theme], bot)), "bot"),
+        Start::Script(name, seed) => (Box::new(GameScene::scripted(&asset_manager, &themes[theme], &name, seed)), "scripted"),
+    };
+    crash::set_scene(scene);
+    limit_framerate(&mut window, options.vsync, scene);
 
-    while window.is_open() {
-        let delta = clock.restart().as_seconds();
+    let mut scene_started = Instant::now();
 
-        if let Some(s) = curscene.update(delta) {
-            match s {
-                State::Menu(hs) => {
-                    curscene = Box::new(MenuScene::new(&asset_manager, hs)) as Box<Scene>;
-                }
-                State::Game => {
-                    curscene = Box::new(GameScene::new(&asset_manager)) as Box<Scene>;
-                }
-                State::Exit => {
-                    window.close()
+    //the hook already wrote the report, the run that was going on is only reachable here
+    let running = panic::catch_unwind(AssertUnwindSafe(|| {
+        while window.is_open() {
+            let delta = clock.restart().as_seconds();
+
+            let next = curscene.update(delta);
+            overlay.update(delta, &curscene.towers());
+            console.update(delta);
+
+            //sent before switching scenes so spectators also see the frame the tower got hit
+            if let Some(ref mut b) = broadcaster {
+                b.send(curscene.snapshot().as_ref());
+            }
+
+            if let Some(s) = next {
+                let switching = Instant::now();
+                let to = s.name();
+
+                match s {
+                    State::Menu(hs) => {
+                        curscene = Box::new(MenuScene::new(&asset_manager, &themes[theme], hs)) as Box<Scene>;
+                    }
+                    State::Game => {
+                        curscene = Box::new(GameScene::new(&asset_manager, &themes[theme])) as Box<Scene>;
+                    }
+                    State::Bot => {
+                        let bot = Box::new(bots::LookaheadBot::new());
+                        curscene = Box::new(GameScene::bot(&asset_manager, &themes[theme], bot)) as Box<Scene>;
+                    }
+                    State::Modes => {
+                        curscene = Box::new(ModeScene::new(&asset_manager, &themes[theme])) as Box<Scene>;
+                    }
+                    State::Scripted(name, seed) => {
+                        curscene = Box::new(GameScene::scripted(&asset_manager, &themes[theme], &name, seed)) as Box<Scene>;
+                    }
+                    State::GameOver(summary) => {
+                        curscene = Box::new(GameOverScene::new(&asset_manager, &themes[theme], summary)) as Box<Scene>;
+                    }
+                    State::Replay(replay) => {
+                        curscene = Box::new(GameScene::replay(&asset_manager, &themes[theme], replay)) as Box<Scene>;
+                    }
+                    State::Stats => {
+                        curscene = Box::new(StatsScene::new(&asset_manager, &themes[theme])) as Box<Scene>;
+                    }
+                    State::Versus => {
+                        curscene = Box::new(VersusScene::new(&asset_manager, &themes[theme])) as Box<Scene>;
+                    }
+                    State::Lobby => {
+                        curscene = Box::new(LobbyScene::new(&asset_manager, &themes[theme])) as Box<Scene>;
+                    }
+                    State::Online(session) => {
+                        curscene = Box::new(NetGameScene::new(&asset_manager, &themes[theme], session)) as Box<Scene>;
+                    }
+                    State::Watch(spectator) => {
+                        curscene = Box::new(GameScene::watch(&asset_manager, &themes[theme], spectator)) as Box<Scene>;
+                    }
+                    State::NextTheme => {
+                        theme = (theme + 1) % themes.len();
+                        curscene = Box::new(MenuScene::new(&asset_manager, &themes[theme], None)) as Box<Scene>;
+                    }
+                    State::Exit => {
+                        window.close()
+                    }
                 }
+
+                info!(Scenes, "{} -> {} after {:.1}s, set up in {:.1} ms",
+                      scene, to, scene_started.elapsed().as_secs_f32(), logging::ms(switching));
+                scene = to;
+                scene_started = Instant::now();
+                crash::set_scene(scene);
+                limit_framerate(&mut window, options.vsync, scene);
             }
-        }
 
-        while let Some(ev) = window.poll_event() {
-            match ev {
-                Event::Closed => { window.close() }
-                Event::KeyPressed {
-                    code, ..
-                } => {
-                    match code {
-                        Key::Escape => { window.close() }
+            while let Some(ev) = window.poll_event() {
+                trace!(Input, "{:?}", ev);
+                crash::record_input(&ev);
+
+                match ev {
+                    Event::Closed => { window.close() }
+                    Event::KeyPressed {
+                        code, ..
+                    } => {
+                        match code {
+                            Key::Escape if console.is_open() => { console.toggle() }
+                            Key::Escape => { window.close() }
+                            Key::F3 => { overlay.toggle() }
+                            Key::Tilde => { console.toggle() }
+                            _ => {}
+                        }
+                    }
+                    Event::MouseButtonPressed {
+                        button: mouse::Button::Right, x, y
+                    } if overlay.visible() => {
+                        let point = window.map_pixel_to_coords(&Vector2i::new(x, y), &curscene.world_view());
+                        overlay.pick(point, &curscene.towers());
+                    }
+                    _ => {}
+                }
+
+                //while the console is open the keyboard belongs to it
+                if console.is_open() {
+                    let line = {
+                        let scene = &curscene;
+                        console.event(ev, |l| scene.complete(l))
+                    };
+
+                    if let Some(line) = line {
+                        match curscene.command(&line) {
+                            Ok(out) => console.print(&out),
+                            Err(e) => console.print(&e),
+                        }
+                    }
+
+                    match ev {
+                        Event::KeyPressed { .. } | Event::KeyReleased { .. } | Event::TextEntered { .. } => continue,
                         _ => {}
                     }
                 }
-                _ => {}
+
+                curscene.events(scaled(ev, &window));
             }
 
-            curscene.events(ev);
+            window.clear(&themes[theme].background);
+
+            curscene.draw(&mut window);
+            overlay.draw(&mut window, &curscene.world_view(), &curscene.towers());
+            console.draw(&mut window);
+
+            window.display();
         }
+    }));
 
-        window.clear(&Color::from(0x777777FF));
+    if let Err(e) = running {
+        if let Some(r) = curscene.recording() {
+            crash::save_replay(r, curscene.recording_complete());
+        }
 
-        curscene.draw(&mut window);
+        panic::resume_unwind(e);
+    }
 
-        window.display();
+    if let Some(result) = stress::result() {
+        report(result);
+    }
+}
+
+//the result of a command, a failed one exits with status 1
+fn report(result: Result<String, String>) {
+    match result {
+        Ok(r) => {
+            if !r.is_empty() {
+                println!("{}", r);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+//the game has no console window of its own on windows, started from a terminal the
+//output of the commands goes to the terminal's
+#[cfg(windows)]
+fn attach_console() {
+    extern "system" {
+        fn AttachConsole(process: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = 0xFFFF_FFFF;
+
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+//the stress test measures how long frames take without waiting for the limit
+fn limit_framerate(win: &mut RenderWindow, vsync: bool, scene: &str) {
+    win.set_framerate_limit(if vsync && scene != "stress" { 60 } else { 0 });
+}
+
+//mouse positions in the coordinates of WIN_SIZE, for windows of another size
+fn scaled(ev: Event, win: &RenderWindow) -> Event {
+    let map = |x: i32, y: i32| {
+        let p = win.map_pixel_to_coords(&Vector2i::new(x, y), &camera::hud_view());
+        (p.x as i32, p.y as i32)
+    };
+
+    match ev {
+        Event::MouseButtonPressed { button, x, y } => {
+            let (x, y) = map(x, y);
+            Event::MouseButtonPressed { button, x, y }
+        }
+        Event::MouseButtonReleased { button, x, y } => {
+            let (x, y) = map(x, y);
+            Event::MouseButtonReleased { button, x, y }
+        }
+        Event::MouseMoved { x, y } => {
+            let (x, y) = map(x, y);
+            Event::MouseMoved { x, y }
+        }
+        Event::MouseWheelScrolled { wheel, delta, x, y } => {
+            let (x, y) = map(x, y);
+            Event::MouseWheelScrolled { wheel, delta, x, y }
+        }
+        e => e,
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,805 @@
+use std::collections::VecDeque;
+use std::env;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sfml::system::Vector2f;
+
+use crate::scenes::score_rate;
+use crate::sim::{TowerInput, TowerState};
+use crate::utils::Rng;
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// Peer to peer games over UDP. Both ends run the same fixed step simulation and only
+// exchange inputs. Inputs of the other player that haven't arrived yet are predicted,
+// when they arrive and differ from the prediction the game is rolled back to that frame
+// and simulated again.
+
+pub const DEFAULT_PORT: u16 = 7777;
+pub const FIXED_DELTA: f32 = 1.0 / 60.0;
+const INPUT_DELAY: usize = 2;
+const MAX_ROLLBACK: usize = 12; //frames we run ahead of the last confirmed remote input
+const MAX_RESEND: usize = 32; //inputs repeated in every packet to get over packet loss
+const HELLO_INTERVAL: Duration = Duration::from_millis(250);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const MAGIC: [u8; 3] = *b"MT1";
+const HELLO: u8 = 1;
+const WELCOME: u8 = 2;
+const INPUTS: u8 = 3;
+const BYE: u8 = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Versus,
+    Coop,
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub struct NetInput {
+    pub aim: Option<(f32, f32)>,
+    pub teleport: bool,
+}
+
+impl NetInput {
+    pub fn apply(&self, tower: &mut TowerState) {
+        if let Some((x, y)) = self.aim {
+            tower.input(TowerInput::Aim(x, y));
+
+            if self.teleport {
+                tower.input(TowerInput::Teleport(x, y));
+            }
+        }
+    }
+
+    //the other player most likely keeps aiming where they did, but won't click again
+    fn predicted(&self) -> NetInput {
+        NetInput {
+            aim: self.aim,
+            teleport: false,
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let (x, y) = self.aim.unwrap_or((0.0, 0.0));
+        let mut flags = 0u8;
+
+        if self.aim.is_some() {
+            flags |= 1;
+        }
+        if self.teleport {
+            flags |= 2;
+        }
+
+        buf.push(flags);
+        buf.extend_from_slice(&x.to_le_bytes());
+        buf.extend_from_slice(&y.to_le_bytes());
+    }
+
+    fn decode(buf: &[u8]) -> NetInput {
+        let x = f32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]);
+        let y = f32::from_le_bytes([buf[5], buf[6], buf[7], buf[8]]);
+
+        NetInput {
+            aim: if buf[0] & 1 != 0 { Some((x, y)) } else { None },
+            teleport: buf[0] & 2 != 0,
+        }
+    }
+}
+
+const INPUT_SIZE: usize = 9;
+
+#[derive(Clone)]
+pub struct World {
+    pub mode: Mode,
+    pub towers: Vec<TowerState>,
+    pub points: [f32; 2],
+    pub frame: u32,
+}
+
+impl World {
+    pub fn new(mode: Mode) -> World {
+        World {
+            mode,
+            towers: vec![
+                TowerState::new(Vector2f::new(WIN_WIDTH / 4.0, WIN_HEIGHT / 2.0), 0.0),
+                TowerState::new(Vector2f::new(WIN_WIDTH * 3.0 / 4.0, WIN_HEIGHT / 2.0), 180.0),
+            ],
+            points: [0.0, 0.0],
+            frame: 0,
+        }
+    }
+
+    pub fn step(&mut self, inputs: [NetInput; 2]) {
+        //both ends have to stop at the same frame, so the frame the game ended on is kept
+        if self.over() {
+            return;
+        }
+
+        self.frame += 1;
+
+        for (t, i) in self.towers.iter_mut().zip(inputs.iter()) {
+            if !t.dead {
+                i.apply(t);
+            }
+        }
+
+        for t in &mut self.towers {
+            t.update(FIXED_DELTA);
+        }
+
+        //in coop the bullets of the partner only count for the score
+        if self.mode == Mode::Versus {
+            let (a, b) = self.towers.split_at_mut(1);
+            a[0].check_hits(&b[0]);
+            b[0].check_hits(&a[0]);
+        }
+
+        let rate = score_rate(self.towers.iter().map(|t| t.num_bullets()).sum());
+        for (p, t) in self.points.iter_mut().zip(self.towers.iter()) {
+            if !t.dead {
+                *p += rate * FIXED_DELTA;
+            }
+        }
+    }
+
+    pub fn over(&self) -> bool {
+        match self.mode {
+            Mode::Versus => self.towers.iter().any(|t| t.dead),
+            Mode::Coop => self.towers.iter().all(|t| t.dead),
+        }
+    }
+
+    //fnv-1a over everything that matters, both ends compare these to find desyncs
+    pub fn checksum(&self) -> u64 {
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut add = |v: u32| {
+            for b in &v.to_le_bytes() {
+                h ^= *b as u64;
+                h = h.wrapping_mul(0x100_0000_01b3);
+            }
+        };
+
+        add(self.frame);
+        for t in &self.towers {
+            add(t.position.x.to_bits());
+            add(t.position.y.to_bits());
+            add(t.rotation.to_bits());
+            add(t.dead as u32);
+
+            for b in &t.bullets {
+                add(b.position.x.to_bits());
+                add(b.position.y.to_bits());
+                add(b.rotation.to_bits());
+            }
+        }
+
+        h
+    }
+}
+
+//a bad connection can be simulated with MOVING_TOWER_NETSIM="latency_ms,jitter_ms,loss_percent"
+#[derive(Clone, Copy, Default)]
+pub struct Conditions {
+    pub latency: f32,
+    pub jitter: f32,
+    pub loss: f32,
+}
+
+impl Conditions {
+    pub fn from_env() -> Conditions {
+        match env::var("MOVING_TOWER_NETSIM") {
+            Ok(v) => Conditions::parse(&v).unwrap_or_default(),
+            Err(_) => Conditions::default(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Conditions> {
+        let v: Vec<f32> = s.split(',').map(|p| p.trim().parse::<f32>()).collect::<Result<_, _>>().ok()?;
+
+        Some(Conditions {
+            latency: v.get(0).cloned().unwrap_or(0.0) / 1000.0,
+            jitter: v.get(1).cloned().unwrap_or(0.0) / 1000.0,
+            loss: v.get(2).cloned().unwrap_or(0.0) / 100.0,
+        })
+    }
+}
+
+//the socket, with outgoing packets delayed or dropped according to the simulated conditions
+struct Link {
+    socket: UdpSocket,
+    conditions: Conditions,
+    delayed: Vec<(Instant, SocketAddr, Vec<u8>)>,
+    rng: Rng,
+}
+
+impl Link {
+    fn bind(addr: SocketAddr, conditions: Conditions) -> io::Result<Link> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Link {
+            socket,
+            conditions,
+            delayed: Vec::new(),
+            rng: Rng::new(addr.port() as u64 + 1),
+        })
+    }
+
+    fn send(&mut self, to: SocketAddr, data: Vec<u8>) {
+        if self.conditions.loss > 0.0 && self.rng.next_f32() < self.conditions.loss {
+            return;
+        }
+
+        let delay = self.conditions.latency + self.conditions.jitter * self.rng.next_f32();
+
+        if delay <= 0.0 {
+            let _ = self.socket.send_to(&data, to);
+        } else {
+            let at = Instant::now() + Duration::from_micros((delay * 1_000_000.0) as u64);
+            self.delayed.push((at, to, data));
+        }
+    }
+
+    fn flush(&mut self) {
+        let now = Instant::now();
+        let mut i = 0;
+
+        while i < self.delayed.len() {
+            if self.delayed[i].0 <= now {
+                let (_, to, data) = self.delayed.swap_remove(i);
+                let _ = self.socket.send_to(&data, to);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn recv(&mut self) -> Option<(Vec<u8>, SocketAddr)> {
+        self.flush();
+
+        let mut buf = [0u8; 1500];
+
+        match self.socket.recv_from(&mut buf) {
+            Ok((n, from)) => Some((buf[..n].to_vec(), from)),
+            Err(_) => None,
+        }
+    }
+}
+
+fn packet(kind: u8) -> Vec<u8> {
+    let mut p = MAGIC.to_vec();
+    p.push(kind);
+
+    p
+}
+
+fn parse(data: &[u8]) -> Option<(u8, &[u8])> {
+    if data.len() < 4 || data[..3] != MAGIC {
+        return None;
+    }
+
+    Some((data[3], &data[4..]))
+}
+
+pub fn with_default_port(addr: &str, port: u16) -> String {
+    if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:{}", addr, port)
+    }
+}
+
+pub fn read_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+#[derive(Clone, Copy)]
+enum Role {
+    Host,
+    Join(SocketAddr),
+}
+
+//waits for the other player, the host listens for a hello and the client sends them
+pub struct Lobby {
+    link: Option<Link>,
+    role: Role,
+    mode: Mode,
+    last_hello: Option<Instant>,
+}
+
+impl Lobby {
+    pub fn host(port: u16, mode: Mode, conditions: Conditions) -> io::Result<Lobby> {
+        Ok(Lobby {
+            link: Some(Link::bind(SocketAddr::from(([0, 0, 0, 0], port)), conditions)?),
+            role: Role::Host,
+            mode,
+            last_hello: None,
+        })
+    }
+
+    //the address can be given without a port, then the default port is used
+    pub fn join(addr: &str, conditions: Conditions) -> io::Result<Lobby> {
+        let addr = with_default_port(addr, DEFAULT_PORT);
+
+        let peer = match addr.to_socket_addrs()?.next() {
+            Some(a) => a,
+            None => {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("Couldn't resolve {}", addr)));
+            }
+        };
+
+        Ok(Lobby {
+            link: Some(Link::bind(SocketAddr::from(([0, 0, 0, 0], 0)), conditions)?),
+            role: Role::Join(peer),
+            mode: Mode::Versus,
+            last_hello: None,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        match self.link {
+            Some(ref l) => l.socket.local_addr().map(|a| a.port()).unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    pub fn poll(&mut self) -> Option<Session> {
+        if let Role::Join(peer) = self.role {
+            let due = match self.last_hello {
+                Some(t) => t.elapsed() >= HELLO_INTERVAL,
+                None => true,
+            };
+
+            if due {
+                self.last_hello = Some(Instant::now());
+                if let Some(ref mut l) = self.link {
+                    l.send(peer, packet(HELLO));
+                }
+            }
+        }
+
+        loop {
+            let (data, from) = match self.link {
+                Some(ref mut l) => l.recv()?,
+                None => {
+                    return None;
+                }
+            };
+
+            match (self.role, parse(&data)) {
+                (Role::Host, Some((HELLO, _))) => {
+                    let mut link = self.link.take()?;
+                    let mut p = packet(WELCOME);
+                    p.push(self.mode as u8);
+                    link.send(from, p);
+
+                    return Some(Session::new(link, from, 0, self.mode));
+                }
+                (Role::Join(peer), Some((WELCOME, body))) if from == peer && !body.is_empty() => {
+                    let mode = if body[0] == Mode::Coop as u8 { Mode::Coop } else { Mode::Versus };
+
+                    return Some(Session::new(self.link.take()?, peer, 1, mode));
+                }
+
+                _ => {}
+            }
+        }
+    }
+}
+
+pub struct Session {
+    link: Link,
+    peer: SocketAddr,
+    local: usize,
+    mode: Mode,
+    world: World,
+    snapshots: VecDeque<World>, //states at the start of the last frames, oldest first
+    local_inputs: Vec<NetInput>,
+    remote_inputs: Vec<Option<NetInput>>,
+    used_remote: Vec<NetInput>, //what each simulated frame ran with for the other player
+    remote_ack: usize, //the peer has all of our inputs before this frame
+    confirmed: usize, //all inputs of the peer before this frame arrived
+    checksums: Vec<u64>, //of the state after every confirmed frame
+    rollbacks: u32,
+    last_heard: Instant,
+    closed: bool,
+}
+
+impl Session {
+    fn new(link: Link, peer: SocketAddr, local: usize, mode: Mode) -> Session {
+        Session {
+            link,
+            peer,
+            local,
+            mode,
+            world: World::new(mode),
+            snapshots: VecDeque::new(),
+            local_inputs: vec![NetInput::default(); INPUT_DELAY],
+            remote_inputs: vec![Some(NetInput::default()); INPUT_DELAY],
+            used_remote: Vec::new(),
+            remote_ack: 0,
+            confirmed: INPUT_DELAY,
+            checksums: Vec::new(),
+            rollbacks: 0,
+            last_heard: Instant::now(),
+            closed: false,
+        }
+    }
+
+    pub fn local(&self) -> usize {
+        self.local
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    //over and every input that lead there is known, so the peer sees the same ending
+    pub fn finished(&self) -> bool {
+        self.world.over() && self.confirmed >= self.world.frame as usize
+    }
+
+    //finished and the peer knows all of our inputs up to the end as well, only then
+    //it is safe to stop sending them
+    pub fn settled(&self) -> bool {
+        self.finished() && self.remote_ack >= self.world.frame as usize
+    }
+
+    pub fn disconnected(&self) -> bool {
+        self.closed || self.last_heard.elapsed() > TIMEOUT
+    }
+
+    //advances the game by one fixed step with the given local input,
+    //returns false if we had to wait for the other player or the game is over and the
+    //input wasn't used. Has to be called until the session settled, so the peer gets
+    //our inputs and acks up to the end
+    pub fn tick(&mut self, input: NetInput) -> bool {
+        if let Some(f) = self.receive() {
+            self.rollback(f);
+        }
+
+        self.record_checksums();
+
+        let advance = !self.world.over() && (self.world.frame as usize) < self.confirmed + MAX_ROLLBACK;
+
+        if advance {
+            self.local_inputs.push(input);
+        }
+
+        self.send_inputs();
+
+        if advance {
+            self.simulate();
+        }
+
+        advance
+    }
+
+    //returns the first frame that ran with a wrong prediction
+    fn receive(&mut self) -> Option<usize> {
+        let mut mispredicted: Option<usize> = None;
+
+        while let Some((data, from)) = self.link.recv() {
+            if from != self.peer {
+                continue;
+            }
+
+            self.last_heard = Instant::now();
+
+            match parse(&data) {
+                Some((HELLO, _)) => {
+                    //our welcome got lost
+                    let mut p = packet(WELCOME);
+                    p.push(self.mode as u8);
+                    self.link.send(self.peer, p);
+                }
+                Some((INPUTS, body)) if body.len() >= 9 => {
+                    let ack = read_u32(&body[0..]) as usize;
+                    let start = read_u32(&body[4..]) as usize;
+                    let count = body[8] as usize;
+
+                    self.remote_ack = self.remote_ack.max(ack);
+
+                    for k in 0..count {
+                        let at = 9 + k * INPUT_SIZE;
+                        if body.len() < at + INPUT_SIZE {
+                            break;
+                        }
+
+                        //nothing honest is that far ahead of what we have, a broken or spoofed
+                        //packet shouldn't make us grow the input list to any size it likes
+                        let f = start + k;
+                        if f > self.confirmed + MAX_ROLLBACK + MAX_RESEND {
+                            break;
+                        }
+
+                        if self.remote_inputs.len() <= f {
+                            self.remote_inputs.resize(f + 1, None);
+                        }
+
+                        if self.remote_inputs[f].is_none() {
+                            let i = NetInput::decode(&body[at..]);
+                            self.remote_inputs[f] = Some(i);
+
+                            if f < self.used_remote.len() && self.used_remote[f] != i {
+                                mispredicted = Some(mispredicted.map_or(f, |m| m.min(f)));
+                            }
+                        }
+                    }
+
+                    while self.remote_inputs.get(self.confirmed).map_or(false, |i| i.is_some()) {
+                        self.confirmed += 1;
+                    }
+                }
+                Some((BYE, _)) => {
+                    self.closed = true;
+                }
+
+                _ => {}
+            }
+        }
+
+        mispredicted
+    }
+
+    fn rollback(&mut self, from: usize) {
+        let current = self.world.frame as usize;
+        let oldest = current - self.snapshots.len();
+
+        //can't happen as we never run further ahead than the snapshots reach
+        if from < oldest {
+            return;
+        }
+
+        self.world = self.snapshots[from - oldest].clone();
+        self.snapshots.truncate(from - oldest);
+
+        //with the right inputs the game may end earlier than predicted
+        while (self.world.frame as usize) < current && !self.world.over() {
+            self.simulate();
+        }
+        self.used_remote.truncate(self.world.frame as usize);
+
+        self.rollbacks += 1;
+    }
+
+    fn simulate(&mut self) {
+        let f = self.world.frame as usize;
+
+        self.snapshots.push_back(self.world.clone());
+        while self.snapshots.len() > MAX_ROLLBACK + 1 {
+            self.snapshots.pop_front();
+        }
+
+        let remote = self.remote_input(f);
+        if f < self.used_remote.len() {
+            self.used_remote[f] = remote;
+        } else {
+            self.used_remote.push(remote);
+        }
+
+        let mut inputs = [NetInput::default(); 2];
+        inputs[self.local] = self.local_inputs[f];
+        inputs[1 - self.local] = remote;
+
+        self.world.step(inputs);
+    }
+
+    fn remote_input(&self, f: usize) -> NetInput {
+        if let Some(Some(i)) = self.remote_inputs.get(f) {
+            return *i;
+        }
+
+        let known = self.remote_inputs.len().min(f);
+        match self.remote_inputs[..known].iter().rev().filter_map(|i| *i).next() {
+            Some(i) => i.predicted(),
+            None => NetInput::default(),
+        }
+    }
+
+    fn record_checksums(&mut self) {
+        let current = self.world.frame as usize;
+        let oldest = current - self.snapshots.len();
+
+        while self.checksums.len() < self.confirmed.min(current) {
+            let after = self.checksums.len() + 1;
+
+            let c = if after == current {
+                self.world.checksum()
+            } else if after >= oldest {
+                self.snapshots[after - oldest].checksum()
+            } else {
+                break;
+            };
+
+            self.checksums.push(c);
+        }
+    }
+
+    //everything the peer hasn't acknowledged yet, oldest first so it can confirm frames in order
+    fn send_inputs(&mut self) {
+        let start = self.remote_ack.min(self.local_inputs.len());
+        let end = self.local_inputs.len().min(start + MAX_RESEND);
+
+        let mut p = packet(INPUTS);
+        p.extend_from_slice(&(self.confirmed as u32).to_le_bytes());
+        p.extend_from_slice(&(start as u32).to_le_bytes());
+        p.push((end - start) as u8);
+
+        for i in &self.local_inputs[start..end] {
+            i.encode(&mut p);
+        }
+
+        let peer = self.peer;
+        self.link.send(peer, p);
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        //straight to the socket, a delayed bye would never leave
+        let _ = self.link.socket.send_to(&packet(BYE), self.peer);
+    }
+}
+
+//plays a host and a client against each other over loopback with scripted inputs and
+//checks that both ends simulated exactly the same game, first for a number of frames of
+//coop and then a versus match to its end over a slow connection
+pub fn loopback_test(conditions: Conditions, frames: usize) -> Result<String, String> {
+    let coop = play_loopback(Mode::Coop, conditions, frames)?;
+
+    //the ending has to get through even when inputs arrive long after they were used
+    let slow = Conditions {
+        latency: conditions.latency.max(0.15),
+        ..conditions
+    };
+    let versus = play_loopback(Mode::Versus, slow, std::usize::MAX)?;
+
+    Ok(format!("Coop: {}\nVersus at {:.0}ms: {}", coop, slow.latency * 1000.0, versus))
+}
+
+//a host and a client connected to each other over loopback
+fn connect_loopback(mode: Mode, conditions: Conditions) -> Result<[Session; 2], String> {
+    let mut host = Lobby::host(0, mode, conditions).map_err(|e| e.to_string())?;
+    let mut join = Lobby::join(&format!("127.0.0.1:{}", host.port()), conditions).map_err(|e| e.to_string())?;
+
+    let started = Instant::now();
+    let (mut a, mut b): (Option<Session>, Option<Session>) = (None, None);
+
+    while a.is_none() || b.is_none() {
+        if started.elapsed() > TIMEOUT {
+            return Err("Couldn't connect over loopback".to_string());
+        }
+
+        //the welcome of the host may still be held back by the simulated latency or lost,
+        //then the session answers the next hello with another one
+        match a {
+            Some(ref mut s) => {
+                s.receive();
+            }
+            None => a = host.poll(),
+        }
+        if b.is_none() {
+            b = join.poll();
+        }
+
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    Ok([a.unwrap(), b.unwrap()])
+}
+
+//plays until both ends confirmed the frames or the match settled
+fn play_loopback(mode: Mode, conditions: Conditions, frames: usize) -> Result<String, String> {
+    let mut sessions = connect_loopback(mode, conditions)?;
+    let started = Instant::now();
+    let mut rngs = [Rng::new(1), Rng::new(2)];
+    let mut pending = [NetInput::default(); 2];
+
+    let done = |s: &Session| s.settled() || s.checksums.len() >= frames;
+
+    while !sessions.iter().all(done) {
+        if started.elapsed() > Duration::from_secs(120) {
+            return Err("Timed out, the peers stopped confirming frames".to_string());
+        }
+        if sessions.iter().any(|s| s.disconnected()) {
+            return Err(format!("A peer lost the connection at frame {}", sessions[0].world.frame));
+        }
+
+        for i in 0..2 {
+            //a teleport stays pending until the session took it
+            if !pending[i].teleport {
+                let aim = (rngs[i].range(0.0, WIN_WIDTH), rngs[i].range(0.0, WIN_HEIGHT));
+                pending[i] = NetInput {
+                    aim: Some(aim),
+                    teleport: rngs[i].next_f32() < 0.03,
+                };
+            }
+
+            if sessions[i].tick(pending[i]) {
+                pending[i] = NetInput::default();
+            }
+        }
+
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    let n = frames.min(sessions[0].checksums.len()).min(sessions[1].checksums.len());
+
+    for f in 0..n {
+        if sessions[0].checksums[f] != sessions[1].checksums[f] {
+            return Err(format!("Desync at frame {}", f));
+        }
+    }
+
+    if sessions.iter().any(|s| s.settled()) {
+        if !sessions.iter().all(|s| s.settled()) {
+            return Err("Only one peer saw the match end".to_string());
+        }
+        if sessions[0].world.checksum() != sessions[1].world.checksum() {
+            return Err(format!("The match ended differently at frames {} and {}", sessions[0].world.frame, sessions[1].world.frame));
+        }
+
+        let dead: Vec<bool> = sessions[0].world.towers.iter().map(|t| t.dead).collect();
+        return Ok(format!("ended at frame {} with dead towers {:?}, {} and {} rollbacks",
+                          sessions[0].world.frame, dead, sessions[0].rollbacks, sessions[1].rollbacks));
+    }
+
+    Ok(format!("{} frames in sync, {} and {} rollbacks", n, sessions[0].rollbacks, sessions[1].rollbacks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BAD: Conditions = Conditions {
+        latency: 0.04,
+        jitter: 0.02,
+        loss: 0.1,
+    };
+
+    #[test]
+    fn coop_stays_in_sync_over_a_bad_connection() {
+        let result = play_loopback(Mode::Coop, BAD, 300);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn versus_ends_the_same_on_both_ends() {
+        let slow = Conditions {
+            latency: 0.15,
+            ..BAD
+        };
+
+        let result = play_loopback(Mode::Versus, slow, std::usize::MAX);
+        assert!(result.as_ref().map_or(false, |r| r.starts_with("ended")), "{:?}", result);
+    }
+
+    #[test]
+    fn ignores_inputs_far_in_the_future() {
+        let [mut host, client] = connect_loopback(Mode::Coop, Conditions::default()).unwrap();
+
+        let mut p = packet(INPUTS);
+        p.extend_from_slice(&0u32.to_le_bytes());
+        p.extend_from_slice(&std::u32::MAX.to_le_bytes());
+        p.push(1);
+        NetInput::default().encode(&mut p);
+        client.link.socket.send_to(&p, client.peer).unwrap();
+
+        let started = Instant::now();
+        while host.last_heard < started && started.elapsed() < Duration::from_secs(1) {
+            host.tick(NetInput::default());
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(host.remote_inputs.len() <= MAX_ROLLBACK + MAX_RESEND + 1);
+    }
+}
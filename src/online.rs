@@ -0,0 +1,325 @@
+use sfml::graphics::*;
+use sfml::window::*;
+use sfml::window::mouse::Button;
+
+use crate::actors::*;
+use crate::assets::AssetManager;
+use crate::broadcast::{Connecting, Spectator, DEFAULT_BROADCAST_PORT};
+use crate::layout::Action;
+use crate::net::*;
+use crate::scenes::{Scene, State};
+use crate::sim::TowerState;
+use crate::theme::Theme;
+use crate::ui::{center_text, Screen, PLAYER_COLORS};
+
+const MAX_STEPS_PER_FRAME: u32 = 5;
+
+pub struct LobbyScene<'a> {
+    screen: Screen<'a>,
+    address_text: Text<'a>,
+    status_text: Text<'a>,
+    address: String,
+    lobby: Option<Lobby>,
+    watching: Option<Connecting>,
+}
+
+impl<'a> LobbyScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme) -> LobbyScene<'a> {
+        let screen = Screen::new(am, theme, am.get_screen("lobby.screen"), &[
+            ("port", DEFAULT_PORT.to_string()),
+            ("broadcast_port", DEFAULT_BROADCAST_PORT.to_string()),
+        ]);
+
+        let address_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.body_size);
+            t.set_fill_color(&theme.text);
+
+            t
+        };
+
+        let status_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.body_size);
+            t.set_fill_color(&theme.text);
+
+            t
+        };
+
+        let mut l = LobbyScene {
+            screen,
+            address_text,
+            status_text,
+            address: String::new(),
+            lobby: None,
+            watching: None,
+        };
+
+        l.set_address(String::from("127.0.0.1"));
+        l.set_status("");
+
+        l
+    }
+
+    fn set_address(&mut self, address: String) {
+        self.address = address;
+        self.address_text.set_string(format!("Address: {}_", self.address).as_str());
+        center_text(&mut self.address_text, 200.0);
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.status_text.set_string(status);
+        center_text(&mut self.status_text, 235.0);
+    }
+
+    fn host(&mut self, mode: Mode) {
+        //the lobby of an earlier click still holds the port
+        self.lobby = None;
+
+        match Lobby::host(DEFAULT_PORT, mode, Conditions::from_env()) {
+            Ok(l) => {
+                self.lobby = Some(l);
+                self.watching = None;
+                self.set_status(&format!("Waiting for the other player on port {}...", DEFAULT_PORT));
+            }
+            Err(e) => {
+                self.lobby = None;
+                self.set_status(&format!("Couldn't host: {}", e));
+            }
+        }
+    }
+
+    fn join(&mut self) {
+        match Lobby::join(&self.address, Conditions::from_env()) {
+            Ok(l) => {
+                self.lobby = Some(l);
+                self.watching = None;
+                let s = format!("Connecting to {}...", self.address);
+                self.set_status(&s);
+            }
+            Err(e) => {
+                self.lobby = None;
+                self.set_status(&format!("Couldn't join: {}", e));
+            }
+        }
+    }
+
+    fn watch(&mut self) {
+        self.lobby = None;
+        self.watching = Some(Spectator::connect_in_background(&self.address));
+
+        let s = format!("Connecting to {} to watch...", self.address);
+        self.set_status(&s);
+    }
+}
+
+impl<'a> Scene for LobbyScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        match self.screen.update(d) {
+            Some(Action::HostVersus) => self.host(Mode::Versus),
+            Some(Action::HostCoop) => self.host(Mode::Coop),
+            Some(Action::Join) => self.join(),
+            Some(Action::Watch) => self.watch(),
+            Some(a) => {
+                return a.state();
+            }
+            None => {}
+        }
+
+        if let Some(result) = self.watching.as_mut().and_then(|w| w.poll()) {
+            self.watching = None;
+
+            match result {
+                Ok(s) => return Some(State::Watch(s)),
+                Err(e) => self.set_status(&format!("Couldn't watch: {}", e)),
+            }
+        }
+
+        if let Some(ref mut l) = self.lobby {
+            if let Some(s) = l.poll() {
+                return Some(State::Online(s));
+            }
+        }
+
+        None
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        self.screen.draw(win);
+        win.draw(&self.address_text);
+        win.draw(&self.status_text);
+    }
+
+    fn events(&mut self, evt: Event) {
+        self.screen.event(evt);
+
+        if let Event::TextEntered { unicode } = evt {
+            let mut a = self.address.clone();
+
+            match unicode {
+                '\u{8}' => {
+                    a.pop();
+                }
+                c if c.is_ascii_alphanumeric() || c == '.' || c == ':' || c == '-' => {
+                    a.push(c);
+                }
+
+                _ => {}
+            }
+
+            self.set_address(a);
+        }
+    }
+}
+
+pub struct NetGameScene<'a> {
+    session: Session,
+    towers: Vec<Tower<'a>>,
+    hud_text: Text<'a>,
+    message_text: Text<'a>,
+    accumulator: f32,
+    aim: Option<(f32, f32)>,
+    teleport: bool,
+    proceed: bool,
+}
+
+impl<'a> NetGameScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme, session: Session) -> NetGameScene<'a> {
+        let towers = session.world().towers.iter().zip(PLAYER_COLORS.iter()).map(|(s, c)| {
+            let mut t = Tower::with_position(am, theme, s.position, s.rotation);
+            t.set_tint(*c);
+
+            t
+        }).collect();
+
+        let hud_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.hud_size);
+            t.set_fill_color(&theme.text);
+
+            t
+        };
+
+        let message_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.body_size);
+            t.set_fill_color(&theme.text);
+
+            t
+        };
+
+        NetGameScene {
+            session,
+            towers,
+            hud_text,
+            message_text,
+            accumulator: 0.0,
+            aim: None,
+            teleport: false,
+            proceed: false,
+        }
+    }
+
+    fn show_message(&mut self, message: &str) {
+        let s = format!("{}\n\nClick to return to the menu", message);
+        self.message_text.set_string(s.as_str());
+        center_text(&mut self.message_text, 250.0);
+    }
+
+    fn result(&self) -> String {
+        let world = self.session.world();
+        let local = self.session.local();
+
+        match self.session.mode() {
+            Mode::Versus => match (world.towers[local].dead, world.towers[1 - local].dead) {
+                (true, true) => "Both got hit, it's a draw".to_string(),
+                (false, true) => "YOU WIN".to_string(),
+                _ => "YOU LOSE".to_string(),
+            },
+            Mode::Coop => format!("Team score: {:.0}", world.points[0] + world.points[1]),
+        }
+    }
+}
+
+impl<'a> Scene for NetGameScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        //leaving drops the session, so wait until the peer has everything to see the ending too
+        if self.proceed && (self.session.settled() || self.session.disconnected()) {
+            return Some(State::Menu(None));
+        }
+
+        if self.session.finished() {
+            let r = self.result();
+            self.show_message(&r);
+        } else if self.session.disconnected() {
+            self.show_message("The connection to the other player was lost");
+        }
+
+        //after the end the ticks only keep our inputs and acks going, the peer may still miss them
+        if !self.session.disconnected() {
+            self.accumulator = (self.accumulator + d).min(FIXED_DELTA * MAX_STEPS_PER_FRAME as f32);
+
+            while self.accumulator >= FIXED_DELTA {
+                self.accumulator -= FIXED_DELTA;
+
+                let input = NetInput {
+                    aim: self.aim,
+                    teleport: self.teleport,
+                };
+
+                if self.session.tick(input) {
+                    self.teleport = false;
+                }
+            }
+        }
+
+        let world = self.session.world();
+        for (t, s) in self.towers.iter_mut().zip(world.towers.iter()) {
+            t.show(s, d);
+        }
+
+        let hud = match self.session.mode() {
+            Mode::Versus => {
+                let local = self.session.local();
+                format!("You {:.0}    {:.0} Other", world.points[local], world.points[1 - local])
+            }
+            Mode::Coop => format!("Team score: {:.0}", world.points[0] + world.points[1]),
+        };
+        self.hud_text.set_string(hud.as_str());
+        center_text(&mut self.hud_text, 5.0);
+
+        None
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        for t in &self.towers {
+            t.draw(win);
+        }
+
+        win.draw(&self.hud_text);
+
+        if self.session.finished() || self.session.disconnected() {
+            win.draw(&self.message_text);
+        }
+    }
+
+    fn events(&mut self, evt: Event) {
+        let over = self.session.finished() || self.session.disconnected();
+
+        match evt {
+            Event::MouseMoved { x, y } => {
+                self.aim = Some((x as f32, y as f32));
+            }
+            Event::MouseButtonPressed { button: Button::Left, x, y } => {
+                if over {
+                    self.proceed = true;
+                } else {
+                    self.aim = Some((x as f32, y as f32));
+                    self.teleport = true;
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn towers(&self) -> Vec<&TowerState> {
+        self.towers.iter().map(|t| t.state()).collect()
+    }
+}
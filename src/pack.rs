@@ -0,0 +1,278 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Asset packs hold a whole resource directory in one file. A pack starts with a text manifest:
+//
+//   moving-tower pack 1
+//   <number of entries>
+//   <crc32 in hex> <size in bytes> <name like scripts/rain.rhai>
+//   ...
+//
+// followed by the contents of the entries one after the other in the same order. Every
+// checksum is checked when the pack is opened, so a damaged or edited file is noticed before
+// anything is loaded from it. The checksums don't protect against someone who rewrites the
+// manifest as well.
+
+const HEADER: &str = "moving-tower pack 1";
+pub const EXTENSION: &str = "pack";
+
+struct Entry {
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+pub struct Pack {
+    path: PathBuf,
+    entries: Vec<Entry>,
+    data: Vec<u8>,
+}
+
+impl Pack {
+    pub fn open(path: &Path) -> Result<Pack, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let error = |what: &str| format!("{}: {}", path.display(), what);
+
+        let mut rest = &bytes[..];
+        let mut line = || -> Result<String, String> {
+            let end = rest.iter().position(|b| *b == b'\n').ok_or_else(|| error("the manifest ends too early"))?;
+            let l = String::from_utf8(rest[..end].to_vec()).map_err(|_| error("the manifest isn't text"))?;
+            rest = &rest[end + 1..];
+
+            Ok(l)
+        };
+
+        if line()? != HEADER {
+            return Err(error("not an asset pack"));
+        }
+
+        //every entry takes at least one line, so a count larger than the file can't be right
+        let count: usize = line()?.trim().parse().map_err(|_| error("broken number of entries"))?;
+        if count > bytes.len() {
+            return Err(error("broken number of entries"));
+        }
+
+        let mut manifest = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let l = line()?;
+            let mut parts = l.splitn(3, ' ');
+
+            let checksum = parts.next().and_then(|c| u32::from_str_radix(c, 16).ok());
+            let size = parts.next().and_then(|s| s.parse::<usize>().ok());
+
+            match (checksum, size, parts.next()) {
+                (Some(c), Some(s), Some(n)) if !n.is_empty() => manifest.push((c, s, n.to_string())),
+                _ => return Err(error(&format!("broken manifest line `{}`", l))),
+            }
+        }
+
+        let data = rest.to_vec();
+        let mut entries = Vec::with_capacity(count);
+        let mut broken = Vec::new();
+        let mut start: usize = 0;
+
+        for (checksum, size, name) in manifest {
+            let end = match start.checked_add(size) {
+                Some(end) if end <= data.len() => end,
+                _ => return Err(error(&format!("{} is cut off", name))),
+            };
+
+            if crc32(&data[start..end]) != checksum {
+                broken.push(name.clone());
+            }
+
+            entries.push(Entry { name, start, end });
+            start = end;
+        }
+
+        if !broken.is_empty() {
+            return Err(error(&format!("the checksums of {} don't match, the pack is damaged", broken.join(", "))));
+        }
+        if start != data.len() {
+            return Err(error("there is more data than the manifest lists"));
+        }
+
+        Ok(Pack {
+            path: path.to_path_buf(),
+            entries,
+            data,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries.iter().find(|e| e.name == name).map(|e| &self.data[e.start..e.end])
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|e| e.name.as_str())
+    }
+}
+
+//`moving-tower pack [dir] [file]`, every file below the directory goes in
+pub fn build(dir: &Path, out: &Path) -> Result<String, String> {
+    let mut files = Vec::new();
+    collect(dir, "", &mut files)?;
+    files.sort();
+
+    let mut manifest = format!("{}\n{}\n", HEADER, files.len());
+    let mut data = Vec::new();
+
+    for name in &files {
+        let bytes = fs::read(dir.join(name)).map_err(|e| format!("{}: {}", dir.join(name).display(), e))?;
+        manifest += &format!("{:08x} {} {}\n", crc32(&bytes), bytes.len(), name);
+        data.extend_from_slice(&bytes);
+    }
+
+    let mut pack = manifest.into_bytes();
+    pack.extend_from_slice(&data);
+    fs::write(out, &pack).map_err(|e| format!("{}: {}", out.display(), e))?;
+
+    //read back, so a pack that was written wrong isn't shipped
+    Pack::open(out)?;
+
+    Ok(format!("Packed {} files from {} into {} ({} bytes)", files.len(), dir.display(), out.display(), pack.len()))
+}
+
+//names are relative to the packed directory and always use `/`, like the names assets are loaded by
+fn collect(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), String> {
+    let here = if prefix.is_empty() { dir.to_path_buf() } else { dir.join(prefix) };
+    let entries = fs::read_dir(&here).map_err(|e| format!("{}: {}", here.display(), e))?;
+
+    for e in entries.filter_map(|e| e.ok()) {
+        let name = match e.file_name().into_string() {
+            Ok(n) => format!("{}{}", prefix, n),
+            Err(n) => return Err(format!("{:?} isn't a name a pack can hold", n)),
+        };
+
+        if e.path().is_dir() {
+            collect(dir, &format!("{}/", name), files)?;
+        } else if e.path().extension().map_or(false, |x| x == EXTENSION) {
+            continue; //an older pack of the same directory
+        } else if name.contains('\n') {
+            return Err(format!("{:?} isn't a name a pack can hold", name));
+        } else {
+            files.push(name);
+        }
+    }
+
+    Ok(())
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+
+        table[i] = c;
+        i += 1;
+    }
+
+    table
+}
+
+//the same CRC-32 zip and png use
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |c, b| CRC_TABLE[((c ^ *b as u32) & 0xFF) as usize] ^ (c >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    //a pack of a small directory, written to a file of its own for every test
+    fn build_pack(test: &str) -> (PathBuf, Vec<u8>) {
+        let dir = env::temp_dir().join(format!("moving-tower-pack-test-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::write(dir.join("menu.screen"), "[text]\nstring = hi\nposition = center 10\n").unwrap();
+        fs::write(dir.join("scripts/rain.rhai"), "fn init() {}\n").unwrap();
+
+        let file = dir.join(format!("test.{}", EXTENSION));
+        build(&dir, &file).unwrap();
+        let bytes = fs::read(&file).unwrap();
+
+        (file, bytes)
+    }
+
+    #[test]
+    fn opens_what_was_built() {
+        let (file, _) = build_pack("open");
+        let pack = Pack::open(&file).unwrap();
+
+        assert_eq!(pack.names().collect::<Vec<_>>(), vec!["menu.screen", "scripts/rain.rhai"]);
+        assert_eq!(pack.get("scripts/rain.rhai"), Some(&b"fn init() {}\n"[..]));
+        assert_eq!(pack.get("rain.rhai"), None);
+    }
+
+    #[test]
+    fn notices_truncated_packs() {
+        let (file, bytes) = build_pack("truncated");
+
+        for len in &[0, 5, HEADER.len() + 3, bytes.len() / 2, bytes.len() - 1] {
+            fs::write(&file, &bytes[..*len]).unwrap();
+            assert!(Pack::open(&file).is_err(), "cut to {} bytes", len);
+        }
+
+        let mut longer = bytes.clone();
+        longer.push(b'x');
+        fs::write(&file, &longer).unwrap();
+        assert!(Pack::open(&file).is_err());
+    }
+
+    #[test]
+    fn notices_flipped_bits() {
+        let (file, bytes) = build_pack("flipped");
+
+        //the data is at the end, every byte of it is covered by a checksum
+        for at in bytes.len() - 20..bytes.len() {
+            let mut flipped = bytes.clone();
+            flipped[at] ^= 0x10;
+            fs::write(&file, &flipped).unwrap();
+
+            let e = Pack::open(&file).err().unwrap_or_default();
+            assert!(e.contains("damaged"), "byte {}: {}", at, e);
+        }
+    }
+
+    #[test]
+    fn rejects_impossible_manifests() {
+        let (file, _) = build_pack("manifest");
+
+        for manifest in &[
+            format!("{}\n{}\n", HEADER, std::usize::MAX),
+            format!("{}\n1\n00000000 {} a\n", HEADER, std::usize::MAX),
+            format!("{}\n2\n00000000 0 a\n00000000 {} b\n", HEADER, std::usize::MAX),
+            format!("{}\n1\n00000000 0\n", HEADER),
+            "not a pack\n0\n".to_string(),
+        ] {
+            fs::write(&file, manifest).unwrap();
+            assert!(Pack::open(&file).is_err(), "{}", manifest);
+        }
+
+        fs::write(&file, format!("{}\n1\n00000000 0 empty\n", HEADER)).unwrap();
+        assert_eq!(Pack::open(&file).unwrap().get("empty"), Some(&[][..]));
+    }
+
+    #[test]
+    fn crc32_matches_zip() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414F_A339);
+    }
+}
@@ -0,0 +1,241 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+use crate::sim::TowerInput;
+
+// A run is replayed by feeding the recorded frame times and inputs back into a tower,
+// the game has no randomness so this reproduces the run exactly. A game mode script is
+// loaded again with the seed its random numbers started from, and the run is played on an
+// arena of the size it was recorded on.
+//
+// On disk a replay is a header line, `script <name>` and `seed <n>` for runs of a game mode,
+// `arena <width> <height>` for runs on another arena than the window, followed by one line per frame: the frame time and its inputs, `a x y` for aiming and
+// `t x y` for teleporting. Floats are written in their shortest exact form so a loaded replay
+// plays out exactly like the recorded one.
+
+const HEADER: &str = "moving-tower replay 1";
+
+#[derive(Clone)]
+pub struct Frame {
+    pub delta: f32,
+    pub inputs: Vec<TowerInput>,
+}
+
+#[derive(Clone)]
+pub struct Replay {
+    frames: Vec<Frame>,
+    pub script: Option<String>, //the game mode script the run was played with
+    pub seed: u64, //what the random numbers of the script started from
+    pub arena: Option<(f32, f32)>, //the size of MOVING_TOWER_ARENA the run was played on
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay {
+            frames: Vec::new(),
+            script: None,
+            seed: 0,
+            arena: None,
+        }
+    }
+
+    pub fn begin_frame(&mut self, delta: f32) {
+        self.frames.push(Frame {
+            delta,
+            inputs: Vec::new(),
+        });
+    }
+
+    //inputs belong to the frame that was last begun
+    pub fn record(&mut self, input: TowerInput) {
+        if self.frames.is_empty() {
+            self.begin_frame(0.0);
+        }
+
+        if let Some(f) = self.frames.last_mut() {
+            f.inputs.push(input);
+        }
+    }
+
+    pub fn frame(&self, i: usize) -> Option<&Frame> {
+        self.frames.get(i)
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut f = File::create(path)?;
+        writeln!(f, "{}", HEADER)?;
+
+        if let Some(ref name) = self.script {
+            writeln!(f, "script {}", name)?;
+            writeln!(f, "seed {}", self.seed)?;
+        }
+        if let Some((w, h)) = self.arena {
+            writeln!(f, "arena {} {}", w, h)?;
+        }
+
+        for frame in &self.frames {
+            write!(f, "{}", frame.delta)?;
+
+            for i in &frame.inputs {
+                match i {
+                    TowerInput::Aim(x, y) => write!(f, " a {} {}", x, y)?,
+                    TowerInput::Teleport(x, y) => write!(f, " t {} {}", x, y)?,
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        f.flush()
+    }
+
+    pub fn load(path: &str) -> Result<Replay, String> {
+        let mut s = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut s)).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut lines = s.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(format!("{} is not a replay", path));
+        }
+
+        let mut replay = Replay::new();
+        let mut settings = true; //until the first frame
+
+        for (n, line) in lines.enumerate() {
+            let error = || format!("{}:{}: broken frame", path, n + 2);
+
+            if settings {
+                if let Some(name) = line.strip_prefix("script ") {
+                    replay.script = Some(name.to_string());
+                    continue;
+                }
+                if let Some(seed) = line.strip_prefix("seed ") {
+                    replay.seed = seed.parse().map_err(|_| format!("{}:{}: broken seed", path, n + 2))?;
+                    continue;
+                }
+                if let Some(size) = line.strip_prefix("arena ") {
+                    let v: Vec<f32> = size.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+                    match v[..] {
+                        [w, h] if w.is_finite() && h.is_finite() && w > 0.0 && h > 0.0 => replay.arena = Some((w, h)),
+                        _ => return Err(format!("{}:{}: broken arena", path, n + 2)),
+                    }
+                    continue;
+                }
+
+                settings = false;
+            }
+
+            let mut parts = line.split_whitespace();
+
+            let delta = parts.next().and_then(|d| d.parse().ok()).ok_or_else(error)?;
+            replay.begin_frame(delta);
+
+            while let Some(kind) = parts.next() {
+                let x = parts.next().and_then(|v| v.parse().ok()).ok_or_else(error)?;
+                let y = parts.next().and_then(|v| v.parse().ok()).ok_or_else(error)?;
+
+                replay.record(match kind {
+                    "a" => TowerInput::Aim(x, y),
+                    "t" => TowerInput::Teleport(x, y),
+                    _ => return Err(error()),
+                });
+            }
+        }
+
+        Ok(replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn temp_path(test: &str) -> String {
+        env::temp_dir().join(format!("moving-tower-replay-test-{}-{}", test, std::process::id()))
+            .to_string_lossy().into_owned()
+    }
+
+    fn bits(r: &Replay) -> Vec<(u32, Vec<(bool, u32, u32)>)> {
+        r.frames.iter().map(|f| {
+            let inputs = f.inputs.iter().map(|i| match *i {
+                TowerInput::Aim(x, y) => (false, x.to_bits(), y.to_bits()),
+                TowerInput::Teleport(x, y) => (true, x.to_bits(), y.to_bits()),
+            }).collect();
+
+            (f.delta.to_bits(), inputs)
+        }).collect()
+    }
+
+    #[test]
+    fn saved_replays_load_bit_exactly() {
+        let awkward = [1.0 / 3.0, 0.1, -0.0, 1e-45, std::f32::MIN_POSITIVE, 16_777_217.0, std::f32::MAX, -123.456];
+
+        let mut replay = Replay::new();
+        replay.record(TowerInput::Aim(0.5, 0.25)); //before the first frame
+        for (n, v) in awkward.iter().enumerate() {
+            replay.begin_frame(*v);
+            replay.record(TowerInput::Aim(*v, awkward[(n + 1) % awkward.len()]));
+            if n % 3 == 0 {
+                replay.record(TowerInput::Teleport(-v, *v / 7.0));
+            }
+        }
+        replay.begin_frame(1.0 / 60.0); //without inputs
+
+        let path = temp_path("roundtrip");
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(bits(&loaded), bits(&replay));
+        assert_eq!((loaded.script, loaded.seed, loaded.arena), (None, 0, None));
+    }
+
+    #[test]
+    fn replays_keep_the_script_seed_and_arena() {
+        let mut replay = Replay::new();
+        replay.script = Some("shrinking ring".to_string());
+        replay.seed = std::u64::MAX;
+        replay.arena = Some((2000.0, 1234.5));
+        replay.begin_frame(0.25);
+        replay.record(TowerInput::Teleport(3.0, 4.0));
+
+        let path = temp_path("scripted");
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(bits(&loaded), bits(&replay));
+        assert_eq!(loaded.script.as_deref(), Some("shrinking ring"));
+        assert_eq!(loaded.seed, std::u64::MAX);
+        assert_eq!(loaded.arena, Some((2000.0, 1234.5)));
+    }
+
+    #[test]
+    fn rejects_broken_replays() {
+        let path = temp_path("broken");
+
+        for (contents, error) in &[
+            ("moving-tower replay 0\n0.1\n", "is not a replay"),
+            ("", "is not a replay"),
+            (&format!("{}\n0.1 a 1\n", HEADER)[..], ":2: broken frame"),
+            (&format!("{}\n0.1\n0.1 a 1 2 t 3\n", HEADER)[..], ":3: broken frame"),
+            (&format!("{}\n0.1 x 1 2\n", HEADER)[..], ":2: broken frame"),
+            (&format!("{}\nfast a 1 2\n", HEADER)[..], ":2: broken frame"),
+            (&format!("{}\n0.1 a 1 north\n", HEADER)[..], ":2: broken frame"),
+            (&format!("{}\nscript rain\nseed -1\n", HEADER)[..], ":3: broken seed"),
+            (&format!("{}\n0.1\nseed 1\n", HEADER)[..], ":3: broken frame"),
+            (&format!("{}\narena 2000\n", HEADER)[..], ":2: broken arena"),
+            (&format!("{}\narena 2000 -5\n", HEADER)[..], ":2: broken arena"),
+            (&format!("{}\narena inf 600\n", HEADER)[..], ":2: broken arena"),
+        ] {
+            fs::write(&path, contents).unwrap();
+            let e = Replay::load(&path).err().unwrap_or_default();
+            assert!(e.ends_with(error), "{:?}: {}", contents, e);
+        }
+
+        let _ = fs::remove_file(&path);
+        assert!(Replay::load(&path).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::pack::{self, Pack};
+use crate::utils::{data_dir, get_path};
+
+// Assets are looked up in a list of roots, the first root that has a file wins. So a player
+// can put their own tower.png into the mods directory and everything else still comes from
+// the install. From first to last:
+//
+//   --resources <dir or pack>
+//   MOVING_TOWER_RESOURCES=<dir or pack>
+//   mods/ in the data directory, like ~/.local/share/moving-tower/mods
+//   the packs in mods/, by name
+//   moving-tower/ in every XDG_DATA_DIRS entry (/usr/local/share and /usr/share without it)
+//   resources/ next to the executable
+//   resources.pack next to the executable
+//   the screens, animations and scripts compiled into the binary, with the `embed-assets`
+//   feature also the font, the textures and the icon, so the game runs as a single file
+
+static OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+//packs are opened and checked once, they stay loaded while the game runs because fonts keep
+//reading from the memory they were loaded from
+static PACKS: Mutex<Vec<(PathBuf, Result<&'static Pack, String>)>> = Mutex::new(Vec::new());
+
+//the text assets the game can't start without, so a broken install still shows the menus
+const EMBEDDED: [(&str, &str); 10] = [
+    ("menu.screen", include_str!("../Assets/menu.screen")),
+    ("gameover.screen", include_str!("../Assets/gameover.screen")),
+    ("stats.screen", include_str!("../Assets/stats.screen")),
+    ("lobby.screen", include_str!("../Assets/lobby.screen")),
+    ("modes.screen", include_str!("../Assets/modes.screen")),
+    ("crash.screen", include_str!("../Assets/crash.screen")),
+    ("tower.anim", include_str!("../Assets/tower.anim")),
+    ("bullet.anim", include_str!("../Assets/bullet.anim")),
+    ("scripts/rain.rhai", include_str!("../Assets/scripts/rain.rhai")),
+    ("scripts/shrinking_ring.rhai", include_str!("../Assets/scripts/shrinking_ring.rhai")),
+];
+
+//the font, textures and icon, see Cargo.toml
+#[cfg(feature = "embed-assets")]
+const EMBEDDED_FILES: [(&str, &[u8]); 4] = [
+    ("font.ttf", include_bytes!("../Assets/font.ttf")),
+    ("tower.png", include_bytes!("../Assets/tower.png")),
+    ("bullet.png", include_bytes!("../Assets/bullet.png")),
+    ("icon.png", include_bytes!("../Assets/icon.png")),
+];
+
+#[cfg(not(feature = "embed-assets"))]
+const EMBEDDED_FILES: [(&str, &[u8]); 0] = [];
+
+pub enum Root {
+    Dir(PathBuf),
+    Pack(&'static Pack),
+    Embedded,
+}
+
+impl fmt::Display for Root {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Root::Dir(d) => write!(f, "{}", d.display()),
+            Root::Pack(p) => write!(f, "{}", p.path().display()),
+            Root::Embedded => write!(f, "the binary"),
+        }
+    }
+}
+
+//where a binary asset like `font.ttf` is loaded from
+pub enum Source {
+    File(PathBuf),
+    Packed(&'static Pack, &'static [u8]),
+    Embedded(&'static [u8]),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::File(p) => write!(f, "{}", p.display()),
+            Source::Packed(p, _) => write!(f, "{}", p.path().display()),
+            Source::Embedded(_) => write!(f, "the binary"),
+        }
+    }
+}
+
+//`--resources` goes before everything else
+pub fn set_override(dir: &Path) {
+    if let Ok(mut o) = OVERRIDE.lock() {
+        *o = Some(dir.to_path_buf());
+    }
+}
+
+pub fn mods_dir() -> PathBuf {
+    data_dir().join("mods")
+}
+
+pub fn roots() -> Vec<Root> {
+    let mut roots = Vec::new();
+
+    if let Some(dir) = OVERRIDE.lock().ok().and_then(|o| o.clone()) {
+        roots.extend(dir_or_pack(dir));
+    }
+
+    if let Some(dir) = env::var_os("MOVING_TOWER_RESOURCES") {
+        roots.extend(dir_or_pack(PathBuf::from(dir)));
+    }
+
+    roots.push(Root::Dir(mods_dir()));
+
+    let mut packs: Vec<PathBuf> = fs::read_dir(mods_dir()).map(|entries| {
+        entries.filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && p.extension().map_or(false, |x| x == pack::EXTENSION))
+            .collect()
+    }).unwrap_or_default();
+    packs.sort();
+    roots.extend(packs.into_iter().filter_map(|p| open_pack(p).ok()).map(Root::Pack));
+
+    if cfg!(unix) && !cfg!(target_os = "macos") {
+        let dirs = env::var("XDG_DATA_DIRS").ok().filter(|d| !d.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+
+        for d in dirs.split(':').filter(|d| !d.is_empty()) {
+            roots.push(Root::Dir(Path::new(d).join("moving-tower")));
+        }
+    }
+
+    roots.push(Root::Dir(PathBuf::from(get_path("resources"))));
+
+    let installed = PathBuf::from(get_path(&format!("resources.{}", pack::EXTENSION)));
+    if installed.is_file() {
+        roots.extend(open_pack(installed).ok().map(Root::Pack));
+    }
+
+    roots.push(Root::Embedded);
+
+    roots
+}
+
+//the file an asset like `font.ttf` or `scripts/rain.rhai` is loaded from, None if no directory has it
+pub fn path(name: &str) -> Option<PathBuf> {
+    roots().into_iter().find_map(|r| match r {
+        Root::Dir(d) if d.join(name).is_file() => Some(d.join(name)),
+        _ => None,
+    })
+}
+
+//like `path`, but also looks into packs and what the binary was built with
+pub fn find(name: &str) -> Option<Source> {
+    roots().into_iter().find_map(|r| match r {
+        Root::Dir(d) if d.join(name).is_file() => Some(Source::File(d.join(name))),
+        Root::Pack(p) => p.get(name).map(|data| Source::Packed(p, data)),
+        Root::Embedded => EMBEDDED_FILES.iter().find(|(n, _)| *n == name).map(|(_, data)| Source::Embedded(data)),
+        _ => None,
+    })
+}
+
+//text assets can also come from the binary, the root they were found in is returned with them
+pub fn read_to_string(name: &str) -> Result<(String, Root), String> {
+    for r in roots() {
+        match r {
+            Root::Dir(ref d) if d.join(name).is_file() => {
+                let p = d.join(name);
+                return fs::read_to_string(&p).map(|s| (s, r)).map_err(|e| format!("{}: {}", p.display(), e));
+            }
+            Root::Pack(p) => {
+                if let Some(data) = p.get(name) {
+                    return String::from_utf8(data.to_vec()).map(|s| (s, r)).map_err(|_| format!("{} in {} isn't text", name, p.path().display()));
+                }
+            }
+            Root::Embedded => {
+                if let Some((_, s)) = EMBEDDED.iter().find(|(n, _)| *n == name) {
+                    return Ok((s.to_string(), r));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(not_found(name))
+}
+
+//the names in a directory like `scripts/` in any root that end with the extension, sorted
+pub fn list(dir: &str, extension: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+
+    for r in roots() {
+        let found: Vec<String> = match r {
+            Root::Dir(d) => match fs::read_dir(d.join(dir)) {
+                Ok(entries) => entries.filter_map(|e| e.ok())
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+            Root::Pack(p) => p.names()
+                .filter_map(|n| n.strip_prefix(dir))
+                .map(|n| n.to_string())
+                .collect(),
+            Root::Embedded => EMBEDDED.iter()
+                .filter_map(|(n, _)| n.strip_prefix(dir))
+                .map(|n| n.to_string())
+                .collect(),
+        };
+
+        names.extend(found.into_iter().filter(|n| n.ends_with(extension)));
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub fn not_found(name: &str) -> String {
+    let searched: Vec<String> = roots().iter().map(|r| r.to_string()).collect();
+    format!("{} isn't in any of {}", name, searched.join(", "))
+}
+
+//the packs given with --resources or MOVING_TOWER_RESOURCES have to open, a player who asked for
+//one shouldn't get a game that quietly loads everything from somewhere else
+pub fn check_requested() -> Result<(), String> {
+    let requested = OVERRIDE.lock().ok().and_then(|o| o.clone()).into_iter()
+        .chain(env::var_os("MOVING_TOWER_RESOURCES").map(PathBuf::from));
+
+    for path in requested.filter(|p| p.is_file()) {
+        open_pack(path)?;
+    }
+
+    Ok(())
+}
+
+//a file given as a resource directory is a pack
+fn dir_or_pack(path: PathBuf) -> Option<Root> {
+    if path.is_file() {
+        open_pack(path).ok().map(Root::Pack)
+    } else {
+        Some(Root::Dir(path))
+    }
+}
+
+//a pack that is damaged is left out, everything in it is then loaded from the other roots
+fn open_pack(path: PathBuf) -> Result<&'static Pack, String> {
+    let mut packs = PACKS.lock().map_err(|_| format!("{}: couldn't open it", path.display()))?;
+
+    if let Some((_, p)) = packs.iter().find(|(p, _)| *p == path) {
+        return p.clone();
+    }
+
+    let opened = match Pack::open(&path) {
+        Ok(p) => {
+            info!(Assets, "opened the pack {}", path.display());
+            Ok(&*Box::leak(Box::new(p)))
+        }
+        Err(e) => {
+            error!(Assets, "{}", e);
+            Err(e)
+        }
+    };
+
+    packs.push((path, opened.clone()));
+
+    opened
+}
+
+//why packs were left out, for `validate-assets`
+pub fn broken_packs() -> Vec<String> {
+    roots(); //opens any pack that wasn't needed yet
+
+    match PACKS.lock() {
+        Ok(packs) => packs.iter().filter_map(|(_, p)| p.as_ref().err().cloned()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, contents: &str) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn found(name: &str) -> Option<PathBuf> {
+        match find(name) {
+            Some(Source::File(p)) => Some(p),
+            _ => None,
+        }
+    }
+
+    //the only test that changes where assets come from, so tests running at the same time
+    //don't see roots of each other
+    #[test]
+    fn earlier_roots_shadow_later_ones() {
+        let base = env::temp_dir().join(format!("moving-tower-roots-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+
+        let layered = cfg!(unix) && !cfg!(target_os = "macos");
+        let saved: Vec<_> = ["XDG_DATA_HOME", "XDG_DATA_DIRS"].iter().map(|k| (*k, env::var_os(k))).collect();
+        let mut roots = vec![("override", base.join("override")), ("env", base.join("env"))];
+        if layered {
+            roots.push(("mods", base.join("data/moving-tower/mods")));
+            roots.push(("xdg", base.join("share/moving-tower")));
+            env::set_var("XDG_DATA_HOME", base.join("data"));
+            env::set_var("XDG_DATA_DIRS", base.join("share"));
+        }
+
+        for (name, dir) in &roots {
+            write(dir, "shadow.txt", name);
+        }
+        write(&roots[0].1, "scripts/shadow.rhai", "");
+        write(&roots[1].1, "scripts/shadow.rhai", "");
+        write(&roots[1].1, "scripts/rain.rhai", "");
+
+        set_override(&roots[0].1);
+        env::set_var("MOVING_TOWER_RESOURCES", &roots[1].1);
+
+        //each root wins until its file is gone
+        for (name, dir) in &roots {
+            assert_eq!(found("shadow.txt"), Some(dir.join("shadow.txt")));
+            assert_eq!(read_to_string("shadow.txt").map(|(s, _)| s), Ok(name.to_string()));
+            fs::remove_file(dir.join("shadow.txt")).unwrap();
+        }
+        assert!(found("shadow.txt").is_none());
+        assert!(read_to_string("shadow.txt").is_err());
+
+        //a file in a directory wins over the one compiled into the binary
+        assert!(read_to_string("scripts/rain.rhai").map_or(false, |(s, r)| s.is_empty() && matches!(r, Root::Dir(_))));
+
+        let scripts = list("scripts/", ".rhai");
+        assert_eq!(scripts.iter().filter(|s| *s == "shadow.rhai").count(), 1);
+        assert_eq!(scripts.iter().filter(|s| *s == "rain.rhai").count(), 1);
+
+        env::remove_var("MOVING_TOWER_RESOURCES");
+        for (k, v) in saved {
+            match v {
+                Some(v) => env::set_var(k, v),
+                None => env::remove_var(k),
+            }
+        }
+        let _ = fs::remove_dir_all(&base);
+    }
+}
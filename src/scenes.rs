@@ -1,97 +1,121 @@
 use std::fs::*;
 use std::io::*;
+use std::mem;
 use std::option::Option;
+use std::rc::Rc;
 
 use sfml::graphics::*;
-use sfml::system::Vector2f;
+use sfml::system::{SfBox, Vector2f};
 use sfml::window::*;
 
 use crate::actors::*;
 use crate::assets::AssetManager;
-use crate::ui::UiButton;
+use crate::bots::Controller;
+use crate::broadcast::{Snapshot, Spectator};
+use crate::camera::{self, Camera};
+use crate::console::Commands;
+use crate::crash;
+use crate::ghost::{Ghost, BEST_REPLAY};
+use crate::layout::Action;
+use crate::net::Session;
+use crate::replay::Replay;
+use crate::script::{self, Script};
+use crate::sim::{Rules, TowerInput, TowerState, RULE_NAMES};
+use crate::stats::{LifetimeStats, RunStats};
+use crate::theme::Theme;
+use crate::tween::{Easing, Tween};
+use crate::ui::{Screen, UiButton};
 
 use super::{WIN_HEIGHT, WIN_WIDTH};
 
-#[derive(PartialEq)]
 pub enum State {
     Menu(Option<f32>),
     Game,
+    Bot,
+    Modes,
+    Scripted(String, u64), //the script and the seed of its random numbers
+    GameOver(RunSummary),
+    Replay(Replay),
+    Stats,
+    Versus,
+    Lobby,
+    Online(Session),
+    Watch(Spectator),
+    NextTheme,
     Exit,
 }
 
+impl State {
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Menu(_) => "menu",
+            State::Game => "game",
+            State::Bot => "bot",
+            State::Modes => "modes",
+            State::Scripted(..) => "scripted",
+            State::GameOver(_) => "game over",
+            State::Replay(_) => "replay",
+            State::Stats => "stats",
+            State::Versus => "versus",
+            State::Lobby => "lobby",
+            State::Online(_) => "online",
+            State::Watch(_) => "watch",
+            State::NextTheme => "next theme",
+            State::Exit => "exit",
+        }
+    }
+}
+
 pub trait Scene {
     fn update(&mut self, d: f32) -> Option<State>; //returns the new state if we need to change
 
     fn draw(&self, win: &mut RenderWindow);
 
     fn events(&mut self, evt: Event);
-}
-
-pub struct MenuScene<'a> {
-    title_text: Text<'a>,
-    help_text: Text<'a>,
-    highscore_text: Text<'a>,
-    play_button: UiButton<'a>,
-    exit_button: UiButton<'a>,
-    highscore: f32,
-}
 
-impl<'a> MenuScene<'a> {
-    pub fn new(am: &'a AssetManager, pre_highscore: Option<f32>) -> MenuScene<'a> {
-        let play_button = UiButton::new(am.get_font("font.ttf"))
-            .bounds(WIN_WIDTH / 2.0 - 200.0, WIN_HEIGHT / 2.0, 400.0, 70.0)
-            .color(Color::WHITE)
-            .border_color(Color::BLACK)
-            .border_thickness(3.0)
-            .text("PLAY")
-            .char_size(42)
-            .text_color(Color::BLACK)
-            .pack();
-
-        let exit_button = UiButton::new(am.get_font("font.ttf"))
-            .bounds(WIN_WIDTH / 2.0 - 200.0, WIN_HEIGHT / 2.0 + 120.0, 400.0, 70.0)
-            .color(Color::WHITE)
-            .border_color(Color::BLACK)
-            .border_thickness(3.0)
-            .text("EXIT")
-            .char_size(42)
-            .text_color(Color::BLACK)
-            .pack();
-
-        let title_text = {
-            let mut t = Text::new("Moving Tower", am.get_font("font.ttf"), 56);
-            t.set_fill_color(&Color::BLACK);
-
-            let p = {
-                let mut v = Vector2f::new(WIN_WIDTH / 2.0, 15.0);
-                v.x -= (t.local_bounds().width / 2.0).trunc();
-                v.x = v.x.trunc();
-
-                v
-            };
+    //what spectators of a broadcast get to see, nothing while in a menu
+    fn snapshot(&self) -> Option<Snapshot> {
+        None
+    }
 
-            t.set_position(p);
+    //the towers the debug overlay shows the hitboxes of
+    fn towers(&self) -> Vec<&TowerState> {
+        Vec::new()
+    }
 
-            t
-        };
+    //the view the towers are drawn with
+    fn world_view(&self) -> SfBox<View> {
+        camera::hud_view()
+    }
 
-        let help_text = {
-            let mut t = Text::new("Click to teleport within the ring\nand don't get hit by a bullet", am.get_font("font.ttf"), 19);
-            t.set_fill_color(&Color::BLACK);
+    //runs a line typed into the developer console, returns what to print
+    fn command(&mut self, _line: &str) -> Result<String, String> {
+        Err("There are no commands here".to_string())
+    }
 
-            let p = {
-                let mut v = Vector2f::new(WIN_WIDTH / 2.0, 120.0);
-                v.x -= (t.local_bounds().width / 2.0).trunc();
-                v.x = v.x.trunc();
+    //the line with the command or argument being typed completed
+    fn complete(&self, line: &str) -> String {
+        line.to_string()
+    }
 
-                v
-            };
+    //the run that is going on, saved with the crash report if the game crashes
+    fn recording(&self) -> Option<&Replay> {
+        None
+    }
 
-            t.set_position(p);
+    //false when the console changed the run, which the replay doesn't hold
+    fn recording_complete(&self) -> bool {
+        true
+    }
+}
 
-            t
-        };
+pub struct MenuScene<'a> {
+    screen: Screen<'a>,
+    highscore: f32,
+}
 
+impl<'a> MenuScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme, pre_highscore: Option<f32>) -> MenuScene<'a> {
         let mut highscore = load_highscore();
         match pre_highscore {
             Some(hs) => {
@@ -102,38 +126,24 @@ impl<'a> MenuScene<'a> {
             _ => {}
         }
 
-        let highscore_text = {
-            let mut t = Text::new(format!("Highscore: {:.0}", highscore).as_str(), am.get_font("font.ttf"), 19);
-            t.set_fill_color(&Color::BLACK);
-
-            let p = {
-                let mut v = Vector2f::new(WIN_WIDTH / 2.0, 200.0);
-                v.x -= (t.local_bounds().width / 2.0).trunc();
-                v.x = v.x.trunc();
-
-                v
-            };
-
-            t.set_position(p);
-
-            t
-        };
+        let screen = Screen::new(am, theme, am.get_screen("menu.screen"), &[
+            ("highscore", format!("{:.0}", highscore)),
+            ("theme", theme.name.to_string()),
+        ]);
 
         MenuScene {
-            title_text,
-            help_text,
-            highscore_text,
-            play_button,
-            exit_button,
+            screen,
             highscore,
         }
     }
 }
 
+const HIGHSCORE_FILE: &str = "highscore.txt";
+
 fn load_highscore() -> f32 {
     let mut hs: f32 = 0.0;
 
-    match File::open("highscore.txt") {
+    match File::open(HIGHSCORE_FILE) {
         Ok(mut f) => {
             let mut s = String::new();
             match f.read_to_string(&mut s) {
@@ -155,122 +165,991 @@ fn load_highscore() -> f32 {
 }
 
 fn save_highscore(hs: f32) {
-    match OpenOptions::new().write(true).open("highscore.txt") {
-        Ok(mut f) => {
-            f.write_all(format!("{}", hs).as_bytes());
-            f.flush();
-        }
-
-        Err(e) => match e.kind() {
-            ErrorKind::NotFound => {
-                match File::create("highscore.txt") {
-                    Ok(mut f) => {
-                        f.write(format!("{}", hs).as_bytes());
-                        f.flush();
-                    }
+    match File::create(HIGHSCORE_FILE).and_then(|mut f| f.write_all(format!("{}", hs).as_bytes())) {
+        Ok(_) => info!(Save, "new highscore {:.0}", hs),
+        Err(e) => error!(Save, "couldn't save the highscore: {}", e),
+    }
+}
 
-                    _ => {
-                        println!("Couldn't save!");
-                    }
-                }
-            }
+//`reset-highscore`, the best run goes too so the ghost doesn't race a record that is gone
+pub fn reset_highscore() -> Result<String, String> {
+    let mut removed = Vec::new();
 
-            _ => {
-                println!("Couldn't save!");
-            }
+    for path in &[HIGHSCORE_FILE, BEST_REPLAY] {
+        match remove_file(path) {
+            Ok(_) => removed.push(*path),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Couldn't remove {}: {}", path, e)),
         }
     }
+
+    info!(Save, "highscore reset");
+
+    Ok(if removed.is_empty() { "There was no highscore".to_string() } else { format!("Removed {}", removed.join(" and ")) })
 }
 
 impl<'a> Scene for MenuScene<'a> {
-    fn update(&mut self, _d: f32) -> Option<State> {
-        if self.play_button.clicked() {
-            return Some(State::Game);
-        }
-
-        if self.exit_button.clicked() {
-            save_highscore(self.highscore);
-            return Some(State::Exit);
+    fn update(&mut self, d: f32) -> Option<State> {
+        match self.screen.update(d) {
+            Some(Action::Exit) => {
+                save_highscore(self.highscore);
+                Some(State::Exit)
+            }
+            Some(a) => a.state(),
+            None => None,
         }
-
-        None
     }
 
     fn draw(&self, win: &mut RenderWindow) {
-        self.play_button.draw(win);
-        self.exit_button.draw(win);
-        win.draw(&self.title_text);
-        win.draw(&self.help_text);
-        win.draw(&self.highscore_text);
+        self.screen.draw(win);
     }
 
     fn events(&mut self, evt: Event) {
-        self.play_button.event(evt);
-        self.exit_button.event(evt);
+        self.screen.event(evt);
     }
 }
 
+//points per second while there are this many bullets flying around
+pub fn score_rate(bullets: usize) -> f32 {
+    Rules::default().score_rate(bullets)
+}
+
+const SCORE_POP_SCALE: f32 = 1.3;
+const SCORE_POP_DURATION: f32 = 0.35;
+const GHOST_COLOR: Color = Color { r: 255, g: 255, b: 255, a: 80 };
+const TELEPORT_SHAKE: f32 = 3.0;
+const DEATH_SHAKE: f32 = 14.0;
+const DEATH_ZOOM: f32 = 0.8;
+const DEATH_DURATION: f32 = 0.7; //the game over screen waits this long so the death can be seen
+const WHEEL_ZOOM_STEP: f32 = 0.15;
+const ARENA_BORDER: f32 = 4.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Player {
+    Human,
+    Replay,
+    Bot,
+}
+
+pub struct RunSummary {
+    pub stats: RunStats,
+    pub replay: Replay,
+    pub player: Player,
+    pub script: Option<String>,
+    pub cheats: bool, //the console changed the game
+}
+
 pub struct GameScene<'a> {
     tower: Tower<'a>,
     score_text: Text<'a>,
+    label_text: Option<Text<'a>>,
     score: f32,
     score_len: usize,
+    score_pop: Tween,
+    stats: RunStats,
+    replay: Replay,
+    playback: Option<Replay>,
+    frame: usize,
+    spectator: Option<Spectator>,
+    leave: bool,
+    bot: Option<Box<Controller>>,
+    ghost: Option<Ghost>,
+    ghost_tower: Tower<'a>,
+    ghost_text: Text<'a>,
+    script: Option<Script>,
+    script_text: Text<'a>,
+    camera: Camera,
+    border: Option<RectangleShape<'a>>,
+    teleports: u32,
+    dying: Option<f32>,
+    timescale: f32,
+    cheats: bool,
+    commands: Rc<Commands<GameScene<'a>>>, //shared so a command can change the scene it is in
+    next: Option<State>,
 }
 
 impl<'a> GameScene<'a> {
-    pub fn new(am: &'a AssetManager) -> GameScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme) -> GameScene<'a> {
+        GameScene::on_arena(am, theme, camera::arena_from_env())
+    }
+
+    //an arena of another size than the window, replays are played on the one they were recorded on
+    fn on_arena(am: &'a AssetManager, theme: &Theme, arena: Option<Vector2f>) -> GameScene<'a> {
         let score_text = {
-            let mut t = Text::new("0.0", am.get_font("font.ttf"), 20);
-            t.set_fill_color(&Color::BLACK);
+            let mut t = Text::new("0.0", am.get_font(theme.font), theme.hud_size);
+            t.set_fill_color(&theme.text);
             t.set_position(Vector2f::new(WIN_WIDTH / 2.0, 5.0));
 
             t
         };
 
+        let ghost_tower = {
+            let mut t = Tower::new(am, theme);
+            t.set_tint(GHOST_COLOR);
+
+            t
+        };
+
+        let ghost_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.hud_size);
+            t.set_fill_color(&theme.text);
+            t.set_position(Vector2f::new(WIN_WIDTH / 2.0, 45.0));
+
+            t
+        };
+
+        let script_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.body_size);
+            t.set_fill_color(&theme.text);
+            t.set_position(Vector2f::new(10.0, WIN_HEIGHT - 35.0));
+
+            t
+        };
+
+        let size = arena.unwrap_or(Vector2f::new(WIN_WIDTH, WIN_HEIGHT));
+
+        let tower = {
+            let mut t = Tower::with_position(am, theme, size / 2.0, 0.0);
+            let rules = Rules {
+                arena_width: size.x,
+                arena_height: size.y,
+                ..t.state().rules()
+            };
+            t.state_mut().set_rules(rules);
+
+            t
+        };
+
+        //edges of an arena that doesn't fit into the window
+        let border = arena.map(|a| {
+            let mut r = RectangleShape::with_size(a);
+            r.set_fill_color(&Color::TRANSPARENT);
+            r.set_outline_color(&theme.ring);
+            r.set_outline_thickness(ARENA_BORDER);
+
+            r
+        });
+
         GameScene {
-            tower: Tower::new(am),
+            tower,
             score_text,
+            label_text: None,
             score: 0.0,
             score_len: 0,
+            score_pop: Tween::fixed(1.0),
+            stats: RunStats::new(),
+            replay: Replay {
+                arena: arena.map(|a| (a.x, a.y)),
+                ..Replay::new()
+            },
+            playback: None,
+            frame: 0,
+            spectator: None,
+            leave: false,
+            bot: None,
+            ghost: if arena.is_none() { Ghost::load() } else { None }, //best runs are played on the normal arena
+            ghost_tower,
+            ghost_text,
+            script: None,
+            script_text,
+            camera: Camera::new(size),
+            border,
+            teleports: 0,
+            dying: None,
+            timescale: 1.0,
+            cheats: false,
+            commands: Rc::new(game_commands()),
+            next: None,
         }
     }
-}
 
-impl<'a> Scene for GameScene<'a> {
-    fn update(&mut self, d: f32) -> Option<State> {
-        self.tower.update(d);
-        self.score += self.tower.num_bullets() as f32 * 4.0 / (self.tower.num_bullets() as f32 / 8.0 + 1.0) * d;
+    fn with_label(am: &'a AssetManager, theme: &Theme, label: &str, arena: Option<Vector2f>) -> GameScene<'a> {
+        let label_text = {
+            let mut t = Text::new(label, am.get_font(theme.font), theme.hud_size);
+            t.set_fill_color(&theme.text);
+            t.set_position(Vector2f::new(10.0, 5.0));
+
+            t
+        };
+
+        let mut g = GameScene::on_arena(am, theme, arena);
+        g.label_text = Some(label_text);
+        g.ghost = None;
+
+        g
+    }
+
+    //plays back a recorded run instead of listening to the mouse
+    pub fn replay(am: &'a AssetManager, theme: &Theme, replay: Replay) -> GameScene<'a> {
+        let mut g = GameScene::with_label(am, theme, "REPLAY", replay.arena.map(|(w, h)| Vector2f::new(w, h)));
+
+        if let Some(ref name) = replay.script {
+            g.load_script(name, replay.seed);
+        }
+        g.playback = Some(replay);
+
+        g
+    }
+
+    //a game mode from resources/scripts, the run doesn't count for the highscore
+    pub fn scripted(am: &'a AssetManager, theme: &Theme, name: &str, seed: u64) -> GameScene<'a> {
+        let mut g = GameScene::with_label(am, theme, &format!("MODE: {}", name), camera::arena_from_env());
+        g.load_script(name, seed);
+        g.replay.script = Some(name.to_string());
+        g.replay.seed = seed;
+
+        g
+    }
+
+    //a script that fails to load leaves the normal game running with the error on screen
+    fn load_script(&mut self, name: &str, seed: u64) {
+        match Script::load(name, seed) {
+            Ok(mut s) => {
+                s.start(self.tower.state_mut());
+                self.script = Some(s);
+            }
+            Err(e) => {
+                warn!(Script, "{}", e);
+                self.script_text.set_string(format!("Script error: {}", e).as_str());
+            }
+        }
+    }
+
+    fn move_camera(&mut self, d: f32) {
+        let teleports = self.tower.state().teleports();
+
+        //compared with the last frame, a broadcast that starts the next run counts from zero again
+        if teleports > self.teleports {
+            let p = self.tower.state().position;
+            debug!(Physics, "teleport {} to {:.0} {:.0}", teleports, p.x, p.y);
+            self.camera.shake(TELEPORT_SHAKE, 0.2);
+        }
+        self.teleports = teleports;
+
+        self.camera.follow(self.tower.state().position);
+        self.camera.update(d);
+    }
+
+    fn run_script(&mut self, d: f32) {
+        if let Some(ref mut s) = self.script {
+            s.update(d, self.score, self.tower.state_mut());
+
+            let line = match s.error() {
+                Some(e) => format!("Script error: {}", e),
+                None => s.message(),
+            };
+            self.script_text.set_string(line.as_str());
+        }
+    }
+
+    //lets a bot play instead of the mouse, the run doesn't count for the highscore
+    pub fn bot(am: &'a AssetManager, theme: &Theme, bot: Box<Controller>) -> GameScene<'a> {
+        let mut g = GameScene::with_label(am, theme, &format!("BOT: {}", bot.name()), camera::arena_from_env());
+        g.bot = Some(bot);
+
+        g
+    }
+
+    //only shows the runs another game broadcasts, a click goes back to the lobby
+    pub fn watch(am: &'a AssetManager, theme: &Theme, spectator: Spectator) -> GameScene<'a> {
+        let mut g = GameScene::with_label(am, theme, "CONNECTED", camera::arena_from_env());
+        g.spectator = Some(spectator);
+
+        g
+    }
+
+    fn set_score(&mut self, score: f32, d: f32) {
+        let before = self.score;
+        self.score = score;
+
+        //popping on every point would keep the text enlarged, so it only pops every ten points
+        if (self.score / 10.0) as u32 != (before / 10.0) as u32 {
+            self.score_pop = Tween::new(SCORE_POP_SCALE, 1.0, SCORE_POP_DURATION, Easing::BackOut);
+        }
+
+        self.score_pop.update(d);
+        let sp = self.score_pop.value();
+        self.score_text.set_scale(Vector2f::new(sp, sp));
 
         let ss = format!("Score: {:.0}", self.score);
         self.score_text.set_string(ss.as_str());
 
+        //the origin sits at the top center so the text grows in place when popping
         if ss.len() != self.score_len {
             self.score_len = ss.len();
 
-            let p = {
-                let mut v = Vector2f::new(WIN_WIDTH / 2.0, 5.0);
-                v.x -= (self.score_text.local_bounds().width / 2.0).trunc();
-                v.x = v.x.trunc();
+            let o = Vector2f::new((self.score_text.local_bounds().width / 2.0).trunc(), 0.0);
+            self.score_text.set_origin(o);
+        }
+    }
+
+    fn follow_broadcast(&mut self, d: f32) -> Option<State> {
+        if self.leave {
+            return Some(State::Lobby);
+        }
+
+        let (score, label) = match self.spectator {
+            Some(ref mut s) => {
+                s.poll();
+
+                match s.snapshot() {
+                    _ if s.closed() => (None, "BROADCAST ENDED - click to leave"),
+                    Some(snap) => {
+                        self.tower.show(&snap.tower, d);
+                        (Some(snap.score), "LIVE")
+                    }
+                    None => (None, "WAITING FOR THE NEXT RUN"),
+                }
+            }
+            None => (None, ""),
+        };
+
+        if let Some(score) = score {
+            self.set_score(score, d);
+        }
 
-                v
+        if let Some(ref mut t) = self.label_text {
+            t.set_string(label);
+        }
+
+        None
+    }
+
+    fn summary(&mut self) -> RunSummary {
+        let (replay, player) = match self.playback.take() {
+            Some(r) => (r, Player::Replay),
+            None => {
+                let player = if self.bot.is_some() { Player::Bot } else { Player::Human };
+                (mem::replace(&mut self.replay, Replay::new()), player)
+            }
+        };
+
+        RunSummary {
+            stats: self.stats.clone(),
+            script: replay.script.clone(),
+            replay,
+            player,
+            cheats: self.cheats,
+        }
+    }
+}
+
+impl<'a> Scene for GameScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        if self.spectator.is_some() {
+            let next = self.follow_broadcast(d);
+            self.move_camera(d);
+
+            return next;
+        }
+
+        if let Some(ref mut left) = self.dying {
+            *left -= d;
+            self.tower.animate(d);
+            self.camera.update(d);
+
+            if *left <= 0.0 {
+                return Some(State::GameOver(self.summary()));
+            }
+
+            return None;
+        }
+
+        if let Some(s) = self.next.take() {
+            return Some(s);
+        }
+
+        //a replay runs on the recorded frame times so it plays out exactly like the original
+        let d = d * self.timescale;
+        let d = match self.playback {
+            Some(ref r) => match r.frame(self.frame) {
+                Some(f) => f.delta,
+                None => {
+                    return Some(State::Menu(None));
+                }
+            },
+            None => {
+                self.replay.begin_frame(d);
+                d
+            }
+        };
+
+        self.tower.update(d);
+
+        let score = self.score + self.tower.state().rules().score_rate(self.tower.num_bullets()) * d;
+        self.set_score(score, d);
+        self.stats.update(d, self.score, self.tower.state());
+        self.run_script(d);
+
+        if let Some(ref mut g) = self.ghost {
+            g.advance_to(self.stats.duration);
+            self.ghost_tower.show(g.state(), d);
+
+            let diff = self.score - g.score();
+            let s = if diff >= 0.0 {
+                format!("{:.0} ahead of your best", diff)
+            } else {
+                format!("{:.0} behind your best", -diff)
             };
 
-            self.score_text.set_position(p);
+            self.ghost_text.set_string(s.as_str());
+            let o = Vector2f::new((self.ghost_text.local_bounds().width / 2.0).trunc(), 0.0);
+            self.ghost_text.set_origin(o);
+        }
+
+        if self.tower.is_dead() {
+            info!(Physics, "the tower was hit after {:.1}s with {} bullets around, score {:.0}",
+                  self.stats.duration, self.tower.num_bullets(), self.score);
+            self.dying = Some(DEATH_DURATION);
+            self.camera.shake(DEATH_SHAKE, DEATH_DURATION);
+            self.camera.zoom_to(self.camera.zoom_target() * DEATH_ZOOM, DEATH_DURATION);
+            self.camera.update(d);
+
+            return None;
         }
 
-        if self.tower.dead {
-            return Some(State::Menu(Some(self.score)));
+        if let Some(ref r) = self.playback {
+            if let Some(f) = r.frame(self.frame) {
+                for i in &f.inputs {
+                    self.tower.input(*i);
+                }
+            }
+
+            self.frame += 1;
         }
 
+        if let Some(ref mut bot) = self.bot {
+            for i in bot.think(self.tower.state(), d).inputs() {
+                self.replay.record(i);
+                self.tower.input(i);
+            }
+        }
+
+        self.move_camera(d);
+
         None
     }
 
     fn draw(&self, win: &mut RenderWindow) {
+        win.set_view(&self.camera.view());
+
+        if let Some(ref b) = self.border {
+            win.draw(b);
+        }
+
+        if let Some(ref g) = self.ghost {
+            if !g.finished() {
+                self.ghost_tower.draw_tower(win);
+            }
+        }
+
         self.tower.draw(win);
+
+        win.set_view(&camera::hud_view());
+
+        if self.ghost.is_some() {
+            win.draw(&self.ghost_text);
+        }
+
         win.draw(&self.score_text);
+        win.draw(&self.script_text);
+
+        if let Some(ref t) = self.label_text {
+            win.draw(t);
+        }
+    }
+
+    fn events(&mut self, evt: Event) {
+        if self.spectator.is_some() {
+            if let Event::MouseButtonPressed { .. } = evt {
+                self.leave = true;
+            }
+
+            return;
+        }
+
+        //zooming only makes sense when the arena doesn't fit into the window
+        if let Event::MouseWheelScrolled { delta, .. } = evt {
+            let zoom = (self.camera.zoom_target() - delta * WHEEL_ZOOM_STEP).max(1.0).min(self.camera.overview_zoom());
+            self.camera.zoom_to(zoom, 0.25);
+        }
+
+        if self.playback.is_some() || self.bot.is_some() || self.dying.is_some() {
+            return;
+        }
+
+        if let Some(i) = TowerInput::from_event(evt) {
+            let i = self.camera.input_to_world(i);
+            self.replay.record(i);
+            self.tower.input(i);
+        }
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot {
+            score: self.score,
+            tower: self.tower.state().clone(),
+        })
+    }
+
+    fn towers(&self) -> Vec<&TowerState> {
+        vec![self.tower.state()]
+    }
+
+    fn world_view(&self) -> SfBox<View> {
+        self.camera.view()
+    }
+
+    fn command(&mut self, line: &str) -> Result<String, String> {
+        if self.spectator.is_some() {
+            return Err("Broadcasts can only be watched".to_string());
+        }
+
+        let commands = self.commands.clone();
+        commands.run(self, line)
+    }
+
+    fn complete(&self, line: &str) -> String {
+        self.commands.complete(line)
+    }
+
+    fn recording(&self) -> Option<&Replay> {
+        match self.playback {
+            Some(ref r) => Some(r),
+            None if self.spectator.is_none() => Some(&self.replay),
+            None => None,
+        }
+    }
+
+    fn recording_complete(&self) -> bool {
+        !self.cheats
+    }
+}
+
+//what the developer console can do while playing, see console.rs. Built once per scene as
+//looking for levels goes through every resource root
+fn game_commands<'a>() -> Commands<GameScene<'a>> {
+    let mut c = Commands::new();
+
+    c.register("spawn_bullet", "spawn_bullet x y angle", spawn_bullet)
+        .register("set", "set rule value", set_rule).arguments(&RULE_NAMES[..])
+        .register("god", "god on|off", god).arguments(&["on", "off"][..])
+        .register("timescale", "timescale factor", timescale)
+        .register("clear_bullets", "clear_bullets", clear_bullets)
+        .register("load_level", "load_level name", load_level).arguments(&script::available()[..]);
+
+    c
+}
+
+fn numbers(args: &[&str], count: usize) -> Result<Vec<f32>, String> {
+    if args.len() != count {
+        return Err(format!("Expected {} numbers", count));
+    }
+
+    //NaN or infinity would stay in the game for the rest of the run
+    args.iter().map(|a| match a.parse::<f32>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(format!("`{}` isn't a finite number", a)),
+    }).collect()
+}
+
+fn spawn_bullet(g: &mut GameScene, args: &[&str]) -> Result<String, String> {
+    let n = numbers(args, 3)?;
+    g.tower.state_mut().spawn_bullet(Vector2f::new(n[0], n[1]), n[2]);
+    g.cheats = true;
+
+    Ok(String::new())
+}
+
+fn set_rule(g: &mut GameScene, args: &[&str]) -> Result<String, String> {
+    let (name, value) = match args {
+        [name, value] => (*name, numbers(&[*value], 1)?[0]),
+        _ => return Err("Expected a rule and a value".to_string()),
+    };
+
+    //checked like the rules scripts set
+    let mut rules = g.tower.state().rules();
+    rules.set(name, value)?;
+    g.tower.state_mut().set_rules(rules);
+    g.cheats = true;
+
+    //the speed is given to bullets when they are fired, the rest counts right away
+    if name == "bullet_speed" {
+        Ok(format!("{} is {}, for bullets fired from now on", name, value))
+    } else {
+        Ok(format!("{} is {}", name, value))
+    }
+}
+
+fn god(g: &mut GameScene, args: &[&str]) -> Result<String, String> {
+    let on = match args {
+        ["on"] => true,
+        ["off"] => false,
+        _ => return Err("Expected on or off".to_string()),
+    };
+
+    g.tower.state_mut().invulnerable = on;
+    g.cheats |= on;
+
+    Ok(format!("god mode {}", args[0]))
+}
+
+fn timescale(g: &mut GameScene, args: &[&str]) -> Result<String, String> {
+    let t = numbers(args, 1)?[0];
+    if t <= 0.0 || t > 10.0 {
+        return Err("The factor has to be above 0 and at most 10".to_string());
+    }
+
+    g.timescale = t;
+    g.cheats |= t != 1.0;
+
+    Ok(String::new())
+}
+
+fn clear_bullets(g: &mut GameScene, _args: &[&str]) -> Result<String, String> {
+    g.tower.state_mut().bullets.clear();
+    g.cheats = true;
+
+    Ok(String::new())
+}
+
+//levels are the game modes in resources/scripts, they start over from the beginning
+fn load_level(g: &mut GameScene, args: &[&str]) -> Result<String, String> {
+    match args {
+        [name] if script::available().iter().any(|a| a == name) => {
+            g.next = Some(State::Scripted(name.to_string(), g.replay.seed));
+            Ok(String::new())
+        }
+        [name] => Err(format!("There is no level called {}", name)),
+        _ => Err("Expected the name of a level".to_string()),
+    }
+}
+
+pub struct GameOverScene<'a> {
+    screen: Screen<'a>,
+    summary: RunSummary,
+}
+
+impl<'a> GameOverScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme, summary: RunSummary) -> GameOverScene<'a> {
+        let run = &summary.stats;
+
+        let record = if summary.cheats {
+            "The console changed this run, so there is no replay of it"
+        } else if summary.player != Player::Human || summary.script.is_some() {
+            ""
+        } else {
+            let mut lifetime = LifetimeStats::load();
+            lifetime.add(run);
+
+            if let Err(e) = lifetime.save().and_then(|_| run.append_to_log()) {
+                error!(Save, "couldn't save the stats: {}", e);
+            }
+
+            let highscore = load_highscore();
+
+            //the ghost races the best run on the normal arena, a larger one makes for other scores
+            if summary.replay.arena.is_none() && run.score > highscore {
+                save_highscore(run.score);
+
+                if let Err(e) = summary.replay.save(BEST_REPLAY) {
+                    error!(Save, "couldn't save the replay of the best run: {}", e);
+                }
+
+                "NEW RECORD!"
+            } else {
+                ""
+            }
+        };
+
+        let closest = if run.closest_call.is_finite() {
+            format!("{:.0} px", run.closest_call)
+        } else {
+            "-".to_string()
+        };
+
+        let mut screen = Screen::new(am, theme, am.get_screen("gameover.screen"), &[
+            ("title", match summary.player {
+                Player::Human => "GAME OVER",
+                Player::Replay => "REPLAY OVER",
+                Player::Bot => "THE BOT GOT HIT",
+            }.to_string()),
+            ("record", record.to_string()),
+            ("score", format!("{:.0}", run.score)),
+            ("time", format!("{:.1}", run.duration)),
+            ("teleports", run.teleports.to_string()),
+            ("bullets", run.peak_bullets.to_string()),
+            ("near_misses", run.near_misses.to_string()),
+            ("closest", closest),
+        ]);
+
+        //replays only hold the input, played back they would show another run
+        if summary.cheats {
+            screen.hide(Action::Replay);
+        }
+        //retry starts a run of the player, not of the bot or the replay
+        if summary.player != Player::Human {
+            screen.hide(Action::Game);
+        }
+
+        GameOverScene {
+            screen,
+            summary,
+        }
+    }
+}
+
+impl<'a> Scene for GameOverScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        match self.screen.update(d) {
+            Some(Action::Replay) => Some(State::Replay(self.summary.replay.clone())),
+            Some(Action::Game) => match self.summary.script {
+                Some(ref name) => Some(State::Scripted(name.clone(), self.summary.replay.seed)),
+                None => Some(State::Game),
+            },
+            Some(a) => a.state(),
+            None => None,
+        }
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        self.screen.draw(win);
+    }
+
+    fn events(&mut self, evt: Event) {
+        self.screen.event(evt);
+    }
+}
+
+pub struct StatsScene<'a> {
+    screen: Screen<'a>,
+}
+
+impl<'a> StatsScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme) -> StatsScene<'a> {
+        let stats = LifetimeStats::load();
+
+        let closest = match stats.closest_call {
+            Some(c) => format!("{:.0} px", c),
+            None => "-".to_string(),
+        };
+
+        let screen = Screen::new(am, theme, am.get_screen("stats.screen"), &[
+            ("runs", stats.runs.to_string()),
+            ("total_time", format_duration(stats.total_time)),
+            ("avg_time", format!("{:.1} s", stats.avg_time())),
+            ("longest_run", format!("{:.1} s", stats.longest_run)),
+            ("best_score", format!("{:.0}", stats.best_score)),
+            ("avg_score", format!("{:.0}", stats.avg_score())),
+            ("teleports", stats.teleports.to_string()),
+            ("avg_teleport_distance", format!("{:.0} px", stats.avg_teleport_distance())),
+            ("near_misses", stats.near_misses.to_string()),
+            ("closest", closest),
+        ]);
+
+        StatsScene {
+            screen,
+        }
+    }
+}
+
+fn format_duration(secs: f32) -> String {
+    let secs = secs as u32;
+
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl<'a> Scene for StatsScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        match self.screen.update(d) {
+            Some(a) => a.state(),
+            None => None,
+        }
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        self.screen.draw(win);
     }
 
     fn events(&mut self, evt: Event) {
-        self.tower.events(evt);
+        self.screen.event(evt);
+    }
+}
+
+const MODE_BUTTON_TOP: f32 = 150.0;
+const MODE_BUTTON_HEIGHT: f32 = 50.0;
+const MODE_BUTTON_GAP: f32 = 12.0;
+const MODE_COLUMNS: usize = 2;
+
+//lists the game modes in resources/scripts, one button each
+pub struct ModeScene<'a> {
+    screen: Screen<'a>,
+    modes: Vec<(UiButton<'a>, String)>,
+}
+
+impl<'a> ModeScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme) -> ModeScene<'a> {
+        let names = script::available();
+
+        let screen = Screen::new(am, theme, am.get_screen("modes.screen"), &[
+            ("hint", if names.is_empty() { "There are no scripts in resources/scripts yet" } else { "" }.to_string()),
+        ]);
+
+        let width = (WIN_WIDTH - 200.0 - MODE_BUTTON_GAP) / MODE_COLUMNS as f32;
+        let modes = names.into_iter().enumerate().map(|(i, name)| {
+            let x = 100.0 + (i % MODE_COLUMNS) as f32 * (width + MODE_BUTTON_GAP);
+            let y = MODE_BUTTON_TOP + (i / MODE_COLUMNS) as f32 * (MODE_BUTTON_HEIGHT + MODE_BUTTON_GAP);
+
+            let b = UiButton::new(am.get_font(theme.font))
+                .bounds(x, y, width, MODE_BUTTON_HEIGHT)
+                .theme(theme)
+                .text(name.to_uppercase().replace('_', " ").as_str())
+                .char_size(theme.body_size)
+                .pack();
+
+            (b, name)
+        }).collect();
+
+        ModeScene {
+            screen,
+            modes,
+        }
+    }
+}
+
+impl<'a> Scene for ModeScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        for (b, name) in &mut self.modes {
+            b.update(d);
+
+            if b.clicked() {
+                return Some(State::Scripted(name.clone(), 0));
+            }
+        }
+
+        match self.screen.update(d) {
+            Some(a) => a.state(),
+            None => None,
+        }
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        self.screen.draw(win);
+
+        for (b, _) in &self.modes {
+            b.draw(win);
+        }
+    }
+
+    fn events(&mut self, evt: Event) {
+        self.screen.event(evt);
+
+        for (b, _) in &mut self.modes {
+            b.event(evt);
+        }
+    }
+}
+
+const REPORT_TOP: f32 = 100.0;
+const REPORT_LINES: usize = 13;
+
+//offers the report of the last crash, see crash.rs
+pub struct CrashScene<'a> {
+    screen: Screen<'a>,
+    report: Vec<String>,
+    report_text: Text<'a>,
+    status_text: Text<'a>,
+    showing: bool,
+    scroll: usize,
+}
+
+impl<'a> CrashScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme, report: &str) -> CrashScene<'a> {
+        let report_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.hud_size / 2);
+            t.set_fill_color(&theme.text);
+            t.set_position(Vector2f::new(40.0, REPORT_TOP));
+
+            t
+        };
+
+        let status_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.hud_size / 2);
+            t.set_fill_color(&theme.text);
+            t.set_position(Vector2f::new(40.0, REPORT_TOP + 240.0));
+
+            t
+        };
+
+        let mut c = CrashScene {
+            screen: Screen::new(am, theme, am.get_screen("crash.screen"), &[]),
+            report: report.lines().map(|l| l.to_string()).collect(),
+            report_text,
+            status_text,
+            showing: false,
+            scroll: 0,
+        };
+        c.update_report();
+
+        c
+    }
+
+    //the panic itself until the report is shown, then a part of the report that can be scrolled
+    fn update_report(&mut self) {
+        let lines: Vec<&str> = if self.showing {
+            self.report.iter().skip(self.scroll).take(REPORT_LINES).map(|l| l.as_str()).collect()
+        } else {
+            self.report.iter()
+                .skip_while(|l| !l.starts_with("panic:"))
+                .take_while(|l| !l.is_empty())
+                .map(|l| l.as_str())
+                .collect()
+        };
+
+        self.report_text.set_string(lines.join("\n").as_str());
+    }
+}
+
+impl<'a> Scene for CrashScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        match self.screen.update(d) {
+            Some(Action::ShowReport) => {
+                self.showing = true;
+                self.update_report();
+                self.status_text.set_string("scroll with the mouse wheel");
+            }
+            Some(Action::SaveReport) => {
+                let s = match crash::save_copy() {
+                    Ok(path) => format!("saved to {}", path.display()),
+                    Err(e) => format!("couldn't save the report: {}", e),
+                };
+                self.status_text.set_string(s.as_str());
+            }
+            Some(a) => {
+                crash::dismiss();
+                return a.state();
+            }
+            None => {}
+        }
+
+        None
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        self.screen.draw(win);
+        win.draw(&self.report_text);
+        win.draw(&self.status_text);
+    }
+
+    fn events(&mut self, evt: Event) {
+        self.screen.event(evt);
+
+        if let Event::MouseWheelScrolled { delta, .. } = evt {
+            if self.showing {
+                let last = self.report.len().saturating_sub(REPORT_LINES);
+                self.scroll = (self.scroll as f32 - delta * 3.0).max(0.0).min(last as f32) as usize;
+                self.update_report();
+            }
+        }
     }
 }
@@ -0,0 +1,303 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST, FLOAT, INT};
+use sfml::system::Vector2f;
+
+use crate::sim::{Rules, TowerState};
+use crate::resources;
+use crate::utils::Rng;
+
+// Game modes and mutators written in Rhai, loaded from resources/scripts/<name>.rhai.
+// A script may define any of these functions, `this` is a map the script can keep its state in:
+//
+//   fn init()              once when the run starts
+//   fn update(dt)          every frame
+//   fn on_teleport(x, y)   after the tower teleported to x, y
+//   fn on_timer(name)      when a timer set with set_timer(name, seconds) runs out
+//
+// and call these to look at and change the game:
+//
+//   tower_x() tower_y() tower_rotation() bullet_count() time() score() arena_width() arena_height()
+//   spawn_bullet(x, y, angle)  clear_bullets()  set_timer(name, seconds)  random()
+//   rule(name)  set_rule(name, value)   with tower_inner, tower_outer, bullet_speed,
+//                                       score_per_bullet or score_falloff as name
+//   show(text)   a line of text at the bottom of the screen
+//
+// Numbers have to be finite, and set_rule refuses values that would break the game like a
+// negative radius or a score_falloff of 0. Both are errors like any other.
+//
+// Scripts run sandboxed, they can't touch files and get stopped when they take too long.
+// When one fails the error is shown in the game and the script stops, the run goes on.
+
+const SCRIPT_DIR: &str = "scripts/";
+const MAX_OPERATIONS: u64 = 500_000; //per call, stops endless loops
+const HOOKS: [&str; 4] = ["init", "update", "on_teleport", "on_timer"];
+
+//what the script sees of the game and what it asked to change, applied after every call
+struct Context {
+    time: f32,
+    score: f32,
+    position: (f32, f32),
+    rotation: f32,
+    bullets: usize,
+    rules: Rules,
+    spawned: Vec<(f32, f32, f32)>,
+    clear: bool,
+    timers: Vec<(String, f32)>,
+    message: String,
+    rng: Rng,
+}
+
+type Shared = Rc<RefCell<Context>>;
+
+pub struct Script {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    this: Dynamic,
+    hooks: Vec<&'static str>,
+    context: Shared,
+    teleports: u32,
+    error: Option<String>,
+}
+
+//scripts in the script folder of any resource root, without the extension
+pub fn available() -> Vec<String> {
+    resources::list(SCRIPT_DIR, ".rhai").into_iter()
+        .map(|n| n.trim_end_matches(".rhai").to_string())
+        .collect()
+}
+
+fn number(v: &Dynamic) -> Result<f32, Box<EvalAltResult>> {
+    let n = if let Ok(f) = v.as_float() {
+        f as f32
+    } else if let Ok(i) = v.as_int() {
+        i as f32
+    } else {
+        return Err(format!("Expected a number, got {}", v.type_name()).into());
+    };
+
+    //NaN or infinity would stay in the game for the rest of the run
+    if n.is_finite() {
+        Ok(n)
+    } else {
+        Err(format!("Expected a finite number, got {}", n).into())
+    }
+}
+
+fn rule_mut<'r>(rules: &'r mut Rules, name: &str) -> Result<&'r mut f32, Box<EvalAltResult>> {
+    rules.by_name(name).ok_or_else(|| format!("There is no rule called {}", name).into())
+}
+
+fn create_engine(context: &Shared) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(10_000);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.disable_symbol("eval");
+    engine.on_print(|s| info!(Script, "{}", s));
+
+    let c = context.clone();
+    engine.register_fn("tower_x", move || c.borrow().position.0 as FLOAT);
+    let c = context.clone();
+    engine.register_fn("tower_y", move || c.borrow().position.1 as FLOAT);
+    let c = context.clone();
+    engine.register_fn("tower_rotation", move || c.borrow().rotation as FLOAT);
+    let c = context.clone();
+    engine.register_fn("bullet_count", move || c.borrow().bullets as INT);
+    let c = context.clone();
+    engine.register_fn("time", move || c.borrow().time as FLOAT);
+    let c = context.clone();
+    engine.register_fn("score", move || c.borrow().score as FLOAT);
+    let c = context.clone();
+    engine.register_fn("arena_width", move || c.borrow().rules.arena_width as FLOAT);
+    let c = context.clone();
+    engine.register_fn("arena_height", move || c.borrow().rules.arena_height as FLOAT);
+
+    let c = context.clone();
+    engine.register_fn("spawn_bullet", move |x: Dynamic, y: Dynamic, angle: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        let b = (number(&x)?, number(&y)?, number(&angle)?);
+        c.borrow_mut().spawned.push(b);
+        Ok(())
+    });
+    let c = context.clone();
+    engine.register_fn("clear_bullets", move || {
+        let mut c = c.borrow_mut();
+        c.clear = true;
+        c.spawned.clear();
+    });
+    let c = context.clone();
+    engine.register_fn("set_timer", move |name: &str, seconds: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        let mut c = c.borrow_mut();
+        let due = c.time + number(&seconds)?;
+
+        c.timers.retain(|(n, _)| n != name);
+        c.timers.push((name.to_string(), due));
+        Ok(())
+    });
+    let c = context.clone();
+    engine.register_fn("random", move || c.borrow_mut().rng.next_f32() as FLOAT);
+    let c = context.clone();
+    engine.register_fn("rule", move |name: &str| -> Result<FLOAT, Box<EvalAltResult>> {
+        let mut rules = c.borrow().rules;
+        Ok(*rule_mut(&mut rules, name)? as FLOAT)
+    });
+    let c = context.clone();
+    engine.register_fn("set_rule", move |name: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        let value = number(&value)?;
+        c.borrow_mut().rules.set(name, value).map_err(|e| e.into())
+    });
+    let c = context.clone();
+    engine.register_fn("show", move |text: &str| {
+        c.borrow_mut().message = text.to_string();
+    });
+
+    engine
+}
+
+impl Script {
+    //the seed makes `random()` return the same numbers again, so a replay of the run matches
+    pub fn load(name: &str, seed: u64) -> Result<Script, String> {
+        let (source, root) = resources::read_to_string(&format!("{}{}.rhai", SCRIPT_DIR, name))
+            .map_err(|e| format!("Couldn't load script: {}", e))?;
+        debug!(Script, "{}.rhai from {}", name, root);
+
+        let context = Rc::new(RefCell::new(Context {
+            time: 0.0,
+            score: 0.0,
+            position: (0.0, 0.0),
+            rotation: 0.0,
+            bullets: 0,
+            rules: Rules::default(),
+            spawned: Vec::new(),
+            clear: false,
+            timers: Vec::new(),
+            message: String::new(),
+            rng: Rng::new(seed),
+        }));
+
+        let engine = create_engine(&context);
+        let ast = engine.compile(&source).map_err(|e| format!("{}.rhai: {}", name, e))?;
+        let hooks = HOOKS.iter().cloned().filter(|h| ast.iter_functions().any(|f| f.name == *h)).collect();
+
+        Ok(Script {
+            name: name.to_string(),
+            engine,
+            ast,
+            scope: Scope::new(),
+            this: Dynamic::from_map(Map::new()),
+            hooks,
+            context,
+            teleports: 0,
+            error: None,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    //the last error, the script doesn't run anymore once there is one
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_ref().map(|e| e.as_str())
+    }
+
+    pub fn message(&self) -> String {
+        self.context.borrow().message.clone()
+    }
+
+    //runs the top level statements and init
+    pub fn start(&mut self, tower: &mut TowerState) {
+        self.observe(tower);
+
+        if let Err(e) = self.engine.run_ast_with_scope(&mut self.scope, &self.ast) {
+            self.fail(&e);
+            return;
+        }
+
+        self.call("init", ());
+        self.apply(tower);
+    }
+
+    pub fn update(&mut self, d: f32, score: f32, tower: &mut TowerState) {
+        if self.error.is_some() {
+            return;
+        }
+
+        self.context.borrow_mut().time += d;
+        self.context.borrow_mut().score = score;
+        self.observe(tower);
+
+        if tower.teleports() > self.teleports {
+            self.teleports = tower.teleports();
+            self.call("on_teleport", (tower.position.x as FLOAT, tower.position.y as FLOAT));
+        }
+
+        self.call("update", (d as FLOAT,));
+
+        let due: Vec<String> = {
+            let mut c = self.context.borrow_mut();
+            let now = c.time;
+            let due = c.timers.iter().filter(|(_, t)| *t <= now).map(|(n, _)| n.clone()).collect();
+            c.timers.retain(|(_, t)| *t > now);
+
+            due
+        };
+
+        for name in due {
+            self.call("on_timer", (name,));
+        }
+
+        self.apply(tower);
+    }
+
+    fn observe(&mut self, tower: &TowerState) {
+        let mut c = self.context.borrow_mut();
+        c.position = (tower.position.x, tower.position.y);
+        c.rotation = tower.rotation;
+        c.bullets = tower.num_bullets();
+        c.rules = tower.rules();
+    }
+
+    fn apply(&mut self, tower: &mut TowerState) {
+        let mut c = self.context.borrow_mut();
+
+        tower.set_rules(c.rules);
+
+        if c.clear {
+            c.clear = false;
+            tower.bullets.clear();
+        }
+
+        for (x, y, a) in c.spawned.drain(..) {
+            tower.spawn_bullet(Vector2f::new(x, y), a);
+        }
+    }
+
+    fn call(&mut self, hook: &str, args: impl FuncArgs) {
+        if self.error.is_some() || !self.hooks.contains(&hook) {
+            return;
+        }
+
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .rewind_scope(false)
+            .bind_this_ptr(&mut self.this);
+
+        if let Err(e) = self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, hook, args) {
+            self.fail(&e);
+        }
+    }
+
+    fn fail(&mut self, e: &EvalAltResult) {
+        let e = format!("{}.rhai: {}", self.name, e);
+        warn!(Script, "{}", e);
+        self.error = Some(e);
+    }
+}
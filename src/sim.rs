@@ -0,0 +1,349 @@
+use sfml::system::Vector2f;
+use sfml::window::Event;
+use sfml::window::mouse::Button;
+
+use crate::bullets::BulletStore;
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// The game rules without anything that needs a window, so the state can be cloned,
+// rolled back and simulated headless. The actors only draw what is in here.
+
+pub const TOWER_INNER: f32 = 100.0;
+pub const TOWER_OUTER: f32 = 250.0;
+pub const BULLET_SPEED: f32 = 85.0;
+pub const SCORE_PER_BULLET: f32 = 4.0;
+pub const SCORE_FALLOFF: f32 = 8.0;
+pub const NEAR_MISS_DISTANCE: f32 = 15.0;
+
+//half the width of tower.png and bullet.png, used when there are no textures to measure
+pub const TOWER_RADIUS: f32 = 33.0;
+pub const BULLET_RADIUS: f32 = 16.0;
+
+//the numbers that decide how the game plays, the defaults are the constants above
+#[derive(Clone, Copy, PartialEq)]
+pub struct Rules {
+    pub tower_inner: f32,
+    pub tower_outer: f32,
+    pub bullet_speed: f32,
+    pub score_per_bullet: f32,
+    pub score_falloff: f32,
+    pub arena_width: f32, //bullets bounce off the edges of the arena, it can be larger than the window
+    pub arena_height: f32,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            tower_inner: TOWER_INNER,
+            tower_outer: TOWER_OUTER,
+            bullet_speed: BULLET_SPEED,
+            score_per_bullet: SCORE_PER_BULLET,
+            score_falloff: SCORE_FALLOFF,
+            arena_width: WIN_WIDTH,
+            arena_height: WIN_HEIGHT,
+        }
+    }
+}
+
+//the rules scripts and the console can change by name
+pub const RULE_NAMES: [&str; 5] = ["tower_inner", "tower_outer", "bullet_speed", "score_per_bullet", "score_falloff"];
+
+impl Rules {
+    pub fn by_name(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "tower_inner" => Some(&mut self.tower_inner),
+            "tower_outer" => Some(&mut self.tower_outer),
+            "bullet_speed" => Some(&mut self.bullet_speed),
+            "score_per_bullet" => Some(&mut self.score_per_bullet),
+            "score_falloff" => Some(&mut self.score_falloff),
+            _ => None,
+        }
+    }
+
+    //changes a rule by name, values that would break the game like a negative radius, an inner
+    //ring outside the outer one or NaN are refused
+    pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+        if !value.is_finite() {
+            return Err(format!("{} can't be {}", name, value));
+        }
+
+        let expected = match name {
+            "tower_inner" if value < 0.0 || value > self.tower_outer => Some(format!("between 0 and tower_outer ({})", self.tower_outer)),
+            "tower_outer" if value <= 0.0 || value < self.tower_inner => Some(format!("above 0 and at least tower_inner ({})", self.tower_inner)),
+            "bullet_speed" | "score_per_bullet" if value < 0.0 => Some("at least 0".to_string()),
+            "score_falloff" if value <= 0.0 => Some("above 0".to_string()),
+            _ => None,
+        };
+
+        if let Some(e) = expected {
+            return Err(format!("{} has to be {}", name, e));
+        }
+
+        match self.by_name(name) {
+            Some(r) => *r = value,
+            None => return Err(format!("There is no rule called {}", name)),
+        }
+
+        Ok(())
+    }
+
+    //points per second, more bullets give more points but each one is worth a little less
+    pub fn score_rate(&self, bullets: usize) -> f32 {
+        bullets as f32 * self.score_per_bullet / (bullets as f32 / self.score_falloff + 1.0)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Circle {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+impl Circle {
+    pub fn new(x: f32, y: f32, radius: f32) -> Circle {
+        Circle { x, y, radius }
+    }
+
+    pub fn is_colliding(&self, other: &Self) -> bool {
+        self.gap(other) < 0.0
+    }
+
+    //distance between the edges of both circles, negative when they overlap
+    pub fn gap(&self, other: &Self) -> f32 {
+        let dis = {
+            let x = self.x - other.x;
+            let y = self.y - other.y;
+
+            f32::sqrt(x * x + y * y)
+        };
+
+        dis - (self.radius + other.radius)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TowerInput {
+    Aim(f32, f32),
+    Teleport(f32, f32),
+}
+
+impl TowerInput {
+    pub fn from_event(evt: Event) -> Option<TowerInput> {
+        match evt {
+            Event::MouseButtonPressed { button: Button::Left, x, y } => Some(TowerInput::Teleport(x as f32, y as f32)),
+            Event::MouseMoved { x, y } => Some(TowerInput::Aim(x as f32, y as f32)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TowerState {
+    pub position: Vector2f,
+    pub rotation: f32,
+    pub hitbox: Circle,
+    pub bullets: BulletStore,
+    bullet_radius: f32,
+    rules: Rules,
+    teleports: u32,
+    teleport_distance: f32,
+    near_misses: u32,
+    closest_call: f32,
+    pub dead: bool,
+    pub invulnerable: bool, //bullets still count as near misses but can't hit, for testing
+}
+
+impl TowerState {
+    pub fn new(position: Vector2f, rotation: f32) -> TowerState {
+        TowerState::with_radii(position, rotation, TOWER_RADIUS, BULLET_RADIUS)
+    }
+
+    pub fn with_radii(position: Vector2f, rotation: f32, radius: f32, bullet_radius: f32) -> TowerState {
+        TowerState {
+            position,
+            rotation,
+            hitbox: Circle::new(position.x, position.y, radius),
+            bullets: BulletStore::new(),
+            bullet_radius,
+            rules: Rules::default(),
+            teleports: 0,
+            teleport_distance: 0.0,
+            near_misses: 0,
+            closest_call: std::f32::INFINITY,
+            dead: false,
+            invulnerable: false,
+        }
+    }
+
+    pub fn with_rules(mut self, rules: Rules) -> TowerState {
+        self.set_rules(rules);
+        self
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    //a new bullet speed only affects bullets fired from now on, the arena and everything else
+    //counts right away
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+        self.bullets.set_arena(Vector2f::new(rules.arena_width, rules.arena_height));
+    }
+
+    //a bullet that wasn't fired by the tower, scripts use this for new kinds of enemies
+    pub fn spawn_bullet(&mut self, position: Vector2f, rotation: f32) {
+        self.bullets.fire(position, rotation, self.bullet_radius, &self.rules);
+    }
+
+    //returns true if the tower teleported
+    pub fn input(&mut self, input: TowerInput) -> bool {
+        match input {
+            TowerInput::Aim(x, y) => {
+                self.rotation = (y - self.position.y).atan2(x - self.position.x).to_degrees();
+
+                false
+            }
+            TowerInput::Teleport(x, y) => {
+                let mut m = Vector2f::new(x - self.position.x, y - self.position.y);
+                let mut l = f32::sqrt(m.x.powi(2) + m.y.powi(2));
+
+                if l == 0.0 {
+                    return false;
+                }
+
+                m.x /= l;
+                m.y /= l;
+                l = f32::min(self.rules.tower_outer, f32::max(l, self.rules.tower_inner));
+                m.x *= l;
+                m.y *= l;
+
+                self.bullets.fire(self.position, self.rotation, self.bullet_radius, &self.rules);
+                self.teleports += 1;
+                self.teleport_distance += l;
+                self.position += m;
+
+                true
+            }
+        }
+    }
+
+    pub fn update(&mut self, d: f32) {
+        self.hitbox.x = self.position.x;
+        self.hitbox.y = self.position.y;
+
+        let contact = self.bullets.update(d, &self.hitbox);
+
+        self.dead |= contact.hit && !self.invulnerable;
+        self.closest_call = self.closest_call.min(contact.closest);
+        self.near_misses += contact.near_misses;
+    }
+
+    //lets the bullets of another tower hit this one, has to be called after both were updated
+    pub fn check_hits(&mut self, other: &TowerState) {
+        if !self.invulnerable && other.bullets.hits(&self.hitbox) {
+            self.dead = true;
+        }
+    }
+
+    pub fn num_bullets(&self) -> usize {
+        self.bullets.len()
+    }
+
+    pub fn teleports(&self) -> u32 {
+        self.teleports
+    }
+
+    pub fn teleport_distance(&self) -> f32 {
+        self.teleport_distance
+    }
+
+    //bullets that came closer than NEAR_MISS_DISTANCE and flew away again
+    pub fn near_misses(&self) -> u32 {
+        self.near_misses
+    }
+
+    //smallest gap there ever was between the tower and a bullet
+    pub fn closest_call(&self) -> f32 {
+        self.closest_call
+    }
+
+    //only what is needed to draw the tower, used to broadcast it to spectators
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        for v in &[self.position.x, self.position.y, self.rotation] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+
+        buf.push(self.dead as u8);
+        buf.extend_from_slice(&self.teleports.to_le_bytes());
+        buf.extend_from_slice(&(self.bullets.len() as u32).to_le_bytes());
+
+        for b in &self.bullets {
+            for v in &[b.position.x, b.position.y, b.rotation] {
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+
+    //returns the tower and the number of bytes it took up
+    pub fn decode(buf: &[u8]) -> Option<(TowerState, usize)> {
+        let word = |i: usize| buf.get(i..i + 4).map(|b| [b[0], b[1], b[2], b[3]]);
+        let float = |i: usize| word(i).map(f32::from_le_bytes);
+
+        let mut t = TowerState::new(Vector2f::new(float(0)?, float(4)?), float(8)?);
+        t.dead = *buf.get(12)? != 0;
+        t.teleports = u32::from_le_bytes(word(13)?);
+
+        let count = u32::from_le_bytes(word(17)?) as usize;
+        let mut i = 21;
+
+        //checked before allocating so a broken packet can't ask for a huge vector
+        if buf.len() < i + count * 12 {
+            return None;
+        }
+
+        for n in 0..count {
+            let o = i + n * 12;
+            let position = Vector2f::new(float(o)?, float(o + 4)?);
+
+            t.bullets.insert(position, float(o + 8)?, BULLET_RADIUS, BULLET_SPEED);
+        }
+        i += count * 12;
+
+        Some((t, i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_refuse_values_that_break_the_game() {
+        let mut rules = Rules::default();
+
+        for (name, value) in &[
+            ("tower_inner", -1.0),
+            ("tower_inner", TOWER_OUTER + 1.0),
+            ("tower_outer", 0.0),
+            ("tower_outer", TOWER_INNER - 1.0),
+            ("bullet_speed", -5.0),
+            ("score_per_bullet", std::f32::NAN),
+            ("score_falloff", 0.0),
+            ("score_falloff", std::f32::INFINITY),
+            ("arena_width", 10.0),
+        ] {
+            assert!(rules.set(name, *value).is_err(), "{} {}", name, value);
+        }
+        assert!(rules == Rules::default());
+
+        rules.set("tower_inner", 0.0).unwrap();
+        rules.set("tower_outer", 0.5).unwrap();
+        rules.set("bullet_speed", 0.0).unwrap();
+        rules.set("score_falloff", 0.001).unwrap();
+        assert!(rules.score_rate(0) == 0.0);
+        assert!(rules.score_rate(10).is_finite());
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Result, Write};
+
+use crate::sim::TowerState;
+
+const STATS_FILE: &str = "stats.txt";
+const RUNS_FILE: &str = "runs.csv";
+const SCORE_SAMPLE_INTERVAL: f32 = 1.0;
+
+#[derive(Clone)]
+pub struct RunStats {
+    pub duration: f32,
+    pub score: f32,
+    pub teleports: u32,
+    pub teleport_distance: f32,
+    pub peak_bullets: usize,
+    pub near_misses: u32,
+    pub closest_call: f32,
+    pub score_curve: Vec<(f32, f32)>,
+}
+
+impl RunStats {
+    pub fn new() -> RunStats {
+        RunStats {
+            duration: 0.0,
+            score: 0.0,
+            teleports: 0,
+            teleport_distance: 0.0,
+            peak_bullets: 0,
+            near_misses: 0,
+            closest_call: std::f32::INFINITY,
+            score_curve: vec![(0.0, 0.0)],
+        }
+    }
+
+    pub fn update(&mut self, d: f32, score: f32, tower: &TowerState) {
+        self.duration += d;
+        self.score = score;
+        self.teleports = tower.teleports();
+        self.teleport_distance = tower.teleport_distance();
+        self.peak_bullets = self.peak_bullets.max(tower.num_bullets());
+        self.near_misses = tower.near_misses();
+        self.closest_call = tower.closest_call();
+
+        let sampled = self.score_curve.len() as f32 * SCORE_SAMPLE_INTERVAL;
+        if self.duration >= sampled {
+            self.score_curve.push((sampled, score));
+        }
+    }
+
+    //each teleport fires exactly one bullet
+    pub fn bullets(&self) -> u32 {
+        self.teleports
+    }
+
+    pub fn avg_teleport_distance(&self) -> f32 {
+        if self.teleports == 0 {
+            0.0
+        } else {
+            self.teleport_distance / self.teleports as f32
+        }
+    }
+
+    //appends the run to a csv file so the score formula can be balanced with real data
+    pub fn append_to_log(&self) -> Result<()> {
+        let new = File::open(RUNS_FILE).is_err();
+        let mut f = OpenOptions::new().create(true).append(true).open(RUNS_FILE)?;
+
+        if new {
+            writeln!(f, "duration,score,teleports,bullets,avg_teleport_distance,peak_bullets,near_misses,closest_call,score_curve")?;
+        }
+
+        let curve: Vec<String> = self.score_curve.iter()
+            .map(|(t, s)| format!("{:.0}:{:.1}", t, s))
+            .collect();
+
+        //empty for runs without bullets, like the lifetime stats leave it out
+        let closest_call = if self.closest_call.is_finite() {
+            format!("{:.1}", self.closest_call)
+        } else {
+            String::new()
+        };
+
+        writeln!(f, "{:.2},{:.1},{},{},{:.1},{},{},{},{}",
+                 self.duration,
+                 self.score,
+                 self.teleports,
+                 self.bullets(),
+                 self.avg_teleport_distance(),
+                 self.peak_bullets,
+                 self.near_misses,
+                 closest_call,
+                 curve.join(" "))?;
+
+        f.flush()
+    }
+}
+
+#[derive(Default)]
+pub struct LifetimeStats {
+    pub runs: u32,
+    pub total_time: f32,
+    pub total_score: f32,
+    pub best_score: f32,
+    pub longest_run: f32,
+    pub teleports: u32,
+    pub teleport_distance: f32,
+    pub near_misses: u32,
+    pub closest_call: Option<f32>,
+}
+
+impl LifetimeStats {
+    pub fn load() -> LifetimeStats {
+        let mut stats = LifetimeStats::default();
+        let mut s = String::new();
+
+        match File::open(STATS_FILE) {
+            Ok(mut f) => {
+                if f.read_to_string(&mut s).is_err() {
+                    return stats;
+                }
+            }
+
+            _ => {
+                return stats;
+            }
+        }
+
+        for line in s.lines() {
+            let mut kv = line.splitn(2, '=');
+
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) => {
+                    let v = v.trim();
+
+                    match k.trim() {
+                        "runs" => stats.runs = v.parse().unwrap_or(0),
+                        "total_time" => stats.total_time = v.parse().unwrap_or(0.0),
+                        "total_score" => stats.total_score = v.parse().unwrap_or(0.0),
+                        "best_score" => stats.best_score = v.parse().unwrap_or(0.0),
+                        "longest_run" => stats.longest_run = v.parse().unwrap_or(0.0),
+                        "teleports" => stats.teleports = v.parse().unwrap_or(0),
+                        "teleport_distance" => stats.teleport_distance = v.parse().unwrap_or(0.0),
+                        "near_misses" => stats.near_misses = v.parse().unwrap_or(0),
+                        "closest_call" => stats.closest_call = v.parse().ok(),
+                        _ => {}
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        stats
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut f = File::create(STATS_FILE)?;
+
+        writeln!(f, "runs={}", self.runs)?;
+        writeln!(f, "total_time={}", self.total_time)?;
+        writeln!(f, "total_score={}", self.total_score)?;
+        writeln!(f, "best_score={}", self.best_score)?;
+        writeln!(f, "longest_run={}", self.longest_run)?;
+        writeln!(f, "teleports={}", self.teleports)?;
+        writeln!(f, "teleport_distance={}", self.teleport_distance)?;
+        writeln!(f, "near_misses={}", self.near_misses)?;
+        if let Some(c) = self.closest_call {
+            writeln!(f, "closest_call={}", c)?;
+        }
+
+        f.flush()
+    }
+
+    pub fn add(&mut self, run: &RunStats) {
+        self.runs += 1;
+        self.total_time += run.duration;
+        self.total_score += run.score;
+        self.best_score = self.best_score.max(run.score);
+        self.longest_run = self.longest_run.max(run.duration);
+        self.teleports += run.teleports;
+        self.teleport_distance += run.teleport_distance;
+        self.near_misses += run.near_misses;
+
+        if run.closest_call.is_finite() {
+            self.closest_call = Some(match self.closest_call {
+                Some(c) => c.min(run.closest_call),
+                None => run.closest_call,
+            });
+        }
+    }
+
+    pub fn avg_score(&self) -> f32 {
+        if self.runs == 0 { 0.0 } else { self.total_score / self.runs as f32 }
+    }
+
+    pub fn avg_time(&self) -> f32 {
+        if self.runs == 0 { 0.0 } else { self.total_time / self.runs as f32 }
+    }
+
+    pub fn avg_teleport_distance(&self) -> f32 {
+        if self.teleports == 0 { 0.0 } else { self.teleport_distance / self.teleports as f32 }
+    }
+}
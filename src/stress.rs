@@ -0,0 +1,151 @@
+use std::sync::Mutex;
+
+use sfml::graphics::*;
+use sfml::system::Vector2f;
+use sfml::window::*;
+
+use crate::actors::*;
+use crate::assets::AssetManager;
+use crate::scenes::{Scene, State};
+use crate::sim::TowerState;
+use crate::theme::Theme;
+use crate::utils::Rng;
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+// `moving-tower stress [bullets]` fills the arena with bullets (10000 by default) that can't
+// hit the tower and measures whether the game still runs at 60 fps. The frame limit is off while
+// it runs, so the frame times are what updating and drawing take. Once the measurement is done
+// the game closes and main prints the result, with exit status 1 if the 1% low didn't keep up,
+// so it can run on a build machine. A click before that goes to the menu.
+
+pub const DEFAULT_STRESS_BULLETS: usize = 10_000;
+const WARMUP: f32 = 1.0; //the first frames are slow while everything gets uploaded
+const MEASURE_TIME: f32 = 10.0;
+const MIN_FPS: f32 = 60.0;
+
+static RESULT: Mutex<Option<Result<String, String>>> = Mutex::new(None);
+
+//the result of a finished measurement, main reports it like a command after the window closed
+pub fn result() -> Option<Result<String, String>> {
+    RESULT.lock().ok().and_then(|mut r| r.take())
+}
+
+pub struct StressScene<'a> {
+    tower: Tower<'a>,
+    info_text: Text<'a>,
+    bullets: usize,
+    elapsed: f32,
+    frames: Vec<f32>,
+    total: f32, //of the frames
+    reported: bool,
+    leave: bool,
+}
+
+impl<'a> StressScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme, bullets: usize) -> StressScene<'a> {
+        let mut tower = Tower::new(am, theme);
+        tower.state_mut().invulnerable = true;
+        let mut rng = Rng::new(1);
+
+        for _ in 0..bullets {
+            let p = Vector2f::new(rng.range(1.0, WIN_WIDTH - 1.0), rng.range(1.0, WIN_HEIGHT - 1.0));
+            tower.state_mut().spawn_bullet(p, rng.range(-180.0, 180.0));
+        }
+
+        let info_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.hud_size);
+            t.set_fill_color(&theme.text);
+            t.set_outline_color(&theme.background);
+            t.set_outline_thickness(2.0);
+            t.set_position(Vector2f::new(10.0, 5.0));
+
+            t
+        };
+
+        StressScene {
+            tower,
+            info_text,
+            bullets,
+            elapsed: 0.0,
+            frames: Vec::new(),
+            total: 0.0,
+            reported: false,
+            leave: false,
+        }
+    }
+
+    fn average_fps(&self) -> f32 {
+        if self.total > 0.0 { self.frames.len() as f32 / self.total } else { 0.0 }
+    }
+
+    //the fps of the slowest percent of frames, sorts the frames so it is only asked for at the end
+    fn low_fps(&mut self) -> f32 {
+        if self.frames.is_empty() {
+            return 0.0;
+        }
+
+        self.frames.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+
+        let slowest = &self.frames[..(self.frames.len() / 100).max(1)];
+        slowest.len() as f32 / slowest.iter().sum::<f32>()
+    }
+}
+
+impl<'a> Scene for StressScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        if self.leave {
+            return Some(State::Menu(None));
+        }
+
+        self.tower.update(d);
+
+        if self.reported {
+            return None;
+        }
+
+        self.elapsed += d;
+        if self.elapsed > WARMUP {
+            self.frames.push(d);
+            self.total += d;
+        }
+
+        if self.elapsed > WARMUP + MEASURE_TIME {
+            self.reported = true;
+
+            let (fps, low) = (self.average_fps(), self.low_fps());
+            let s = format!("{} bullets: {:.1} fps on average, 1% low {:.1} fps over {} frames", self.bullets, fps, low, self.frames.len());
+            info!(Scenes, "stress: {}", s);
+
+            if let Ok(mut r) = RESULT.lock() {
+                *r = Some(if low < MIN_FPS {
+                    Err(format!("{}, below {:.0} fps", s, MIN_FPS))
+                } else {
+                    Ok(s)
+                });
+            }
+
+            return Some(State::Exit);
+        }
+
+        let s = format!("{} bullets\n{:.1} fps\nclick to leave", self.tower.num_bullets(), self.average_fps());
+        self.info_text.set_string(s.as_str());
+
+        None
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        self.tower.draw(win);
+        win.draw(&self.info_text);
+    }
+
+    fn events(&mut self, evt: Event) {
+        if let Event::MouseButtonPressed { .. } = evt {
+            self.leave = true;
+        }
+    }
+
+    fn towers(&self) -> Vec<&TowerState> {
+        vec![self.tower.state()]
+    }
+}
@@ -0,0 +1,187 @@
+use sfml::graphics::Color;
+
+#[derive(Clone, Copy)]
+pub struct WidgetStyle {
+    pub fill: Color,
+    pub border: Color,
+    pub text: Color,
+    pub scale: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct ButtonStyle {
+    pub normal: WidgetStyle,
+    pub hover: WidgetStyle,
+    pub pressed: WidgetStyle,
+    pub disabled: WidgetStyle,
+    pub border_thickness: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FontSize {
+    Title,
+    Body,
+    Button,
+    Hud,
+    Points(u32),
+}
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub name: &'static str,
+    pub font: &'static str,
+    pub background: Color,
+    pub text: Color,
+    pub ring: Color,
+    pub title_size: u32,
+    pub body_size: u32,
+    pub button_size: u32,
+    pub hud_size: u32,
+    pub button: ButtonStyle,
+    pub hover_duration: f32,
+    pub press_duration: f32,
+}
+
+impl Theme {
+    pub fn all() -> Vec<Theme> {
+        vec![Theme::light(), Theme::dark(), Theme::high_contrast()]
+    }
+
+    pub fn light() -> Theme {
+        let normal = WidgetStyle {
+            fill: Color::WHITE,
+            border: Color::BLACK,
+            text: Color::BLACK,
+            scale: 1.0,
+        };
+
+        let hover = WidgetStyle {
+            fill: Color::rgb(229, 229, 229),
+            ..normal
+        };
+
+        Theme {
+            name: "LIGHT",
+            font: "font.ttf",
+            background: Color::from(0x777777FF),
+            text: Color::BLACK,
+            ring: Color::rgba(255, 255, 255, 50),
+            title_size: 56,
+            body_size: 19,
+            button_size: 42,
+            hud_size: 20,
+            button: ButtonStyle {
+                normal,
+                hover,
+                pressed: WidgetStyle {
+                    scale: 0.97,
+                    ..hover
+                },
+                disabled: WidgetStyle {
+                    fill: Color::rgb(187, 187, 187),
+                    border: Color::rgb(85, 85, 85),
+                    text: Color::rgb(85, 85, 85),
+                    scale: 1.0,
+                },
+                border_thickness: 3.0,
+            },
+            hover_duration: 0.1,
+            press_duration: 0.08,
+        }
+    }
+
+    pub fn dark() -> Theme {
+        let normal = WidgetStyle {
+            fill: Color::rgb(48, 49, 52),
+            border: Color::rgb(138, 180, 248),
+            text: Color::rgb(232, 234, 237),
+            scale: 1.0,
+        };
+
+        Theme {
+            name: "DARK",
+            font: "font.ttf",
+            background: Color::rgb(32, 33, 36),
+            text: Color::rgb(232, 234, 237),
+            ring: Color::rgba(138, 180, 248, 40),
+            title_size: 56,
+            body_size: 19,
+            button_size: 42,
+            hud_size: 20,
+            button: ButtonStyle {
+                normal,
+                hover: WidgetStyle {
+                    fill: Color::rgb(60, 64, 67),
+                    ..normal
+                },
+                pressed: WidgetStyle {
+                    fill: Color::rgb(40, 41, 44),
+                    scale: 0.97,
+                    ..normal
+                },
+                disabled: WidgetStyle {
+                    fill: Color::rgb(42, 42, 42),
+                    border: Color::rgb(85, 85, 85),
+                    text: Color::rgb(119, 119, 119),
+                    scale: 1.0,
+                },
+                border_thickness: 3.0,
+            },
+            hover_duration: 0.1,
+            press_duration: 0.08,
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        let hover = WidgetStyle {
+            fill: Color::YELLOW,
+            border: Color::YELLOW,
+            text: Color::BLACK,
+            scale: 1.0,
+        };
+
+        Theme {
+            name: "HIGH CONTRAST",
+            font: "font.ttf",
+            background: Color::BLACK,
+            text: Color::WHITE,
+            ring: Color::rgba(255, 255, 0, 90),
+            title_size: 60,
+            body_size: 22,
+            button_size: 44,
+            hud_size: 24,
+            button: ButtonStyle {
+                normal: WidgetStyle {
+                    fill: Color::BLACK,
+                    border: Color::WHITE,
+                    text: Color::WHITE,
+                    scale: 1.0,
+                },
+                hover,
+                pressed: WidgetStyle {
+                    scale: 0.95,
+                    ..hover
+                },
+                disabled: WidgetStyle {
+                    fill: Color::BLACK,
+                    border: Color::rgb(128, 128, 128),
+                    text: Color::rgb(128, 128, 128),
+                    scale: 1.0,
+                },
+                border_thickness: 4.0,
+            },
+            hover_duration: 0.0,
+            press_duration: 0.0,
+        }
+    }
+
+    pub fn size(&self, size: FontSize) -> u32 {
+        match size {
+            FontSize::Title => self.title_size,
+            FontSize::Body => self.body_size,
+            FontSize::Button => self.button_size,
+            FontSize::Hud => self.hud_size,
+            FontSize::Points(p) => p,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::f32::consts::PI;
+
+use sfml::graphics::Color;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    ElasticOut,
+    BackOut,
+}
+
+impl Easing {
+    //maps the progress t (0 to 1) onto the eased progress, elastic and back overshoot 1
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Easing::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+                }
+            }
+            Easing::BackOut => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Tween {
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+    easing: Easing,
+}
+
+impl Tween {
+    pub fn new(from: f32, to: f32, duration: f32, easing: Easing) -> Tween {
+        Tween {
+            from,
+            to,
+            duration,
+            elapsed: 0.0,
+            easing,
+        }
+    }
+
+    //a tween that already arrived at its value
+    pub fn fixed(value: f32) -> Tween {
+        Tween::new(value, value, 0.0, Easing::Linear)
+    }
+
+    pub fn update(&mut self, d: f32) {
+        self.elapsed = (self.elapsed + d).min(self.duration);
+    }
+
+    pub fn value(&self) -> f32 {
+        if self.finished() {
+            return self.to;
+        }
+
+        self.from + (self.to - self.from) * self.easing.apply(self.elapsed / self.duration)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    pub fn target(&self) -> f32 {
+        self.to
+    }
+
+    //starts a new tween from the current value, so interrupted animations don't jump
+    pub fn retarget(&mut self, to: f32, duration: f32, easing: Easing) {
+        *self = Tween::new(self.value(), to, duration, easing);
+    }
+}
+
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+pub fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let c = |x: u8, y: u8| lerp(x as f32, y as f32, t).round().max(0.0).min(255.0) as u8;
+
+    Color::rgba(c(a.r, b.r), c(a.g, b.g), c(a.b, b.b), c(a.a, b.a))
+}
@@ -3,29 +3,51 @@ use sfml::system::Vector2f;
 use sfml::window::Event;
 use sfml::window::mouse::Button;
 
+use crate::assets::AssetManager;
+use crate::layout::{Action, Coord, ScreenLayout};
+use crate::theme::{ButtonStyle, Theme, WidgetStyle};
+use crate::tween::*;
+
+use super::WIN_WIDTH;
+
+//the towers of player one and two, in versus and online
+pub const PLAYER_COLORS: [Color; 2] = [
+    Color { r: 255, g: 160, b: 160, a: 255 },
+    Color { r: 160, g: 190, b: 255, a: 255 },
+];
+
 pub struct UiButton<'a> {
     shape: RectangleShape<'a>,
     text: Text<'a>,
     rect: FloatRect,
     clicked: bool,
     down: bool,
-    fill_color: Color,
-    border_color: Color,
+    hover: bool,
+    enabled: bool,
+    style: ButtonStyle,
+    hover_amount: Tween,
+    press_amount: Tween,
+    hover_duration: f32,
+    press_duration: f32,
 }
 
-const DOWN_SCALE: f32 = 0.97;
-const DARKENING_SCALE: f32 = 0.9;
-
 impl<'a> UiButton<'a> {
     pub fn new(font: &'a Font) -> UiButton<'a> {
+        let theme = Theme::light();
+
         UiButton {
             shape: RectangleShape::new(),
             text: Text::new("", font, 16),
             rect: FloatRect::default(),
             clicked: false,
             down: false,
-            fill_color: Color::WHITE,
-            border_color: Color::BLACK,
+            hover: false,
+            enabled: true,
+            style: theme.button,
+            hover_amount: Tween::fixed(0.0),
+            press_amount: Tween::fixed(0.0),
+            hover_duration: theme.hover_duration,
+            press_duration: theme.press_duration,
         }
     }
 
@@ -35,18 +57,27 @@ impl<'a> UiButton<'a> {
         self
     }
 
+    //takes over all styles and sizes of the theme, call before overriding single values
+    pub fn theme(mut self, theme: &Theme) -> Self {
+        self.style = theme.button;
+        self.hover_duration = theme.hover_duration;
+        self.press_duration = theme.press_duration;
+        self.text.set_character_size(theme.button_size);
+        self
+    }
+
     pub fn color(mut self, color: Color) -> Self {
-        self.fill_color = color;
+        self.style.normal.fill = color;
         self
     }
 
     pub fn border_color(mut self, color: Color) -> Self {
-        self.border_color = color;
+        self.style.normal.border = color;
         self
     }
 
     pub fn border_thickness(mut self, thickness: f32) -> Self {
-        self.shape.set_outline_thickness(thickness);
+        self.style.border_thickness = thickness;
         self
     }
 
@@ -61,13 +92,17 @@ impl<'a> UiButton<'a> {
     }
 
     pub fn text_color(mut self, color: Color) -> Self {
-        self.text.set_fill_color(&color);
+        self.style.normal.text = color;
+        self
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.set_enabled(enabled);
         self
     }
 
     pub fn pack(mut self) -> Self {
-        self.shape.set_fill_color(&self.fill_color);
-        self.shape.set_outline_color(&self.border_color);
+        self.shape.set_outline_thickness(self.style.border_thickness);
 
         let pos = {
             let x = self.rect.left
@@ -91,10 +126,28 @@ impl<'a> UiButton<'a> {
         self.shape.set_origin(org);
         self.shape.set_position(Vector2f::new(self.rect.left + org.x, self.rect.top + org.y));
 
+        self.apply_style();
+
         self
     }
 
-    //TODO: add more down state features
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.down = false;
+            self.clicked = false;
+        }
+
+        self.animate();
+    }
+
+    pub fn update(&mut self, d: f32) {
+        self.hover_amount.update(d);
+        self.press_amount.update(d);
+        self.apply_style();
+    }
+
     pub fn draw(&self, win: &mut RenderWindow) {
         win.draw(&self.shape);
         win.draw(&self.text);
@@ -109,51 +162,198 @@ impl<'a> UiButton<'a> {
         false
     }
 
+    fn current_style(&self) -> WidgetStyle {
+        if !self.enabled {
+            return self.style.disabled;
+        }
+
+        let h = blend(&self.style.normal, &self.style.hover, self.hover_amount.value());
+        blend(&h, &self.style.pressed, self.press_amount.value())
+    }
+
+    //retargets the hover and press animations after the button state changed
+    fn animate(&mut self) {
+        let hover = if self.enabled && (self.hover || self.down) { 1.0 } else { 0.0 };
+        if self.hover_amount.target() != hover {
+            self.hover_amount.retarget(hover, self.hover_duration, Easing::QuadOut);
+        }
+
+        let press = if self.enabled && self.down { 1.0 } else { 0.0 };
+        if self.press_amount.target() != press {
+            //releasing springs back slightly past the normal size
+            let easing = if self.down { Easing::QuadOut } else { Easing::BackOut };
+            self.press_amount.retarget(press, self.press_duration, easing);
+        }
+
+        self.apply_style();
+    }
+
+    fn apply_style(&mut self) {
+        let s = self.current_style();
+
+        self.shape.set_fill_color(&s.fill);
+        self.shape.set_outline_color(&s.border);
+        self.shape.set_scale(Vector2f::new(s.scale, s.scale));
+        self.text.set_fill_color(&s.text);
+    }
+
     pub fn event(&mut self, evt: Event) {
         match evt {
             Event::MouseButtonPressed { button: Button::Left, x, y } => {
-                if self.rect.contains2(x as f32, y as f32) {
-                    self.shape.set_scale(Vector2f::new(DOWN_SCALE, DOWN_SCALE));
+                if self.enabled && self.rect.contains2(x as f32, y as f32) {
                     self.down = true;
                 }
             }
             Event::MouseButtonReleased { button: Button::Left, x, y } => {
-                if self.down && self.rect.contains2(x as f32, y as f32) {
+                if self.enabled && self.down && self.rect.contains2(x as f32, y as f32) {
                     self.clicked = true;
                 }
 
-                self.shape.set_scale(Vector2f::new(1.0, 1.0));
                 self.down = false;
             }
             Event::MouseMoved { x, y } => {
-                if self.rect.contains2(x as f32, y as f32) {
-                    let fc = {
-                        let mut c = self.fill_color.clone();
-                        c.r = (c.r as f32 * DARKENING_SCALE) as u8;
-                        c.g = (c.g as f32 * DARKENING_SCALE) as u8;
-                        c.b = (c.b as f32 * DARKENING_SCALE) as u8;
-
-                        c
-                    };
-
-                    let bc = {
-                        let mut c = self.border_color.clone();
-                        c.r = (c.r as f32 * DARKENING_SCALE) as u8;
-                        c.g = (c.g as f32 * DARKENING_SCALE) as u8;
-                        c.b = (c.b as f32 * DARKENING_SCALE) as u8;
-
-                        c
-                    };
-
-                    self.shape.set_fill_color(&fc);
-                    self.shape.set_outline_color(&bc);
-                } else {
-                    self.shape.set_fill_color(&self.fill_color);
-                    self.shape.set_outline_color(&self.border_color);
+                self.hover = self.rect.contains2(x as f32, y as f32);
+            }
+
+            _ => {
+                return;
+            }
+        }
+
+        self.animate();
+    }
+}
+
+fn blend(a: &WidgetStyle, b: &WidgetStyle, t: f32) -> WidgetStyle {
+    WidgetStyle {
+        fill: lerp_color(a.fill, b.fill, t),
+        border: lerp_color(a.border, b.border, t),
+        text: lerp_color(a.text, b.text, t),
+        scale: lerp(a.scale, b.scale, t),
+    }
+}
+
+pub fn center_text(t: &mut Text, y: f32) {
+    let p = {
+        let mut v = Vector2f::new(WIN_WIDTH / 2.0, y);
+        v.x -= (t.local_bounds().width / 2.0).trunc();
+        v.x = v.x.trunc();
+
+        v
+    };
+
+    t.set_position(p);
+}
+
+pub struct Screen<'a> {
+    texts: Vec<Text<'a>>,
+    buttons: Vec<(UiButton<'a>, Action)>,
+}
+
+impl<'a> Screen<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme, layout: &ScreenLayout, vars: &[(&str, String)]) -> Screen<'a> {
+        let texts = layout.texts.iter().map(|def| {
+            let s = substitute(&def.string, vars);
+            let font = def.font.as_ref().map(|f| f.as_str()).unwrap_or(theme.font);
+            let mut t = Text::new(s.as_str(), am.get_font(font), theme.size(def.char_size));
+            t.set_fill_color(&def.color.unwrap_or(theme.text));
+
+            let p = match def.x {
+                Coord::Center => {
+                    let mut v = Vector2f::new(WIN_WIDTH / 2.0, def.y);
+                    v.x -= (t.local_bounds().width / 2.0).trunc();
+                    v.x = v.x.trunc();
+
+                    v
                 }
+                Coord::Value(x) => Vector2f::new(x, def.y),
+            };
+
+            t.set_position(p);
+
+            t
+        }).collect();
+
+        let buttons = layout.buttons.iter().map(|def| {
+            let x = match def.x {
+                Coord::Center => WIN_WIDTH / 2.0 - def.width / 2.0,
+                Coord::Value(x) => x,
+            };
+
+            let font = def.font.as_ref().map(|f| f.as_str()).unwrap_or(theme.font);
+            let mut b = UiButton::new(am.get_font(font))
+                .bounds(x, def.y, def.width, def.height)
+                .theme(theme)
+                .text(substitute(&def.text, vars).as_str())
+                .char_size(theme.size(def.char_size));
+
+            if let Some(c) = def.color {
+                b = b.color(c);
+            }
+            if let Some(c) = def.border_color {
+                b = b.border_color(c);
             }
+            if let Some(t) = def.border_thickness {
+                b = b.border_thickness(t);
+            }
+            if let Some(c) = def.text_color {
+                b = b.text_color(c);
+            }
+
+            let b = b.pack();
 
-            _ => {}
+            (b, def.action)
+        }).collect();
+
+        Screen {
+            texts,
+            buttons,
         }
     }
-}
\ No newline at end of file
+
+    //leaves out the buttons with an action the scene can't do
+    pub fn hide(&mut self, action: Action) {
+        self.buttons.retain(|(_, a)| *a != action);
+    }
+
+    //returns the action of the button that was clicked since the last call
+    pub fn update(&mut self, d: f32) -> Option<Action> {
+        for (b, _) in &mut self.buttons {
+            b.update(d);
+        }
+
+        for (b, a) in &mut self.buttons {
+            if b.clicked() {
+                return Some(*a);
+            }
+        }
+
+        None
+    }
+
+    pub fn draw(&self, win: &mut RenderWindow) {
+        for (b, _) in &self.buttons {
+            b.draw(win);
+        }
+
+        for t in &self.texts {
+            win.draw(t);
+        }
+    }
+
+    pub fn event(&mut self, evt: Event) {
+        for (b, _) in &mut self.buttons {
+            b.event(evt);
+        }
+    }
+}
+
+fn substitute(s: &str, vars: &[(&str, String)]) -> String {
+    let mut s = s.to_string();
+
+    for (k, v) in vars {
+        s = s.replace(&format!("{{{}}}", k), v);
+    }
+
+    s
+}
@@ -1,4 +1,5 @@
 use std::env;
+use std::path::PathBuf;
 
 pub fn get_path(path: &str) -> String {
     let here = {
@@ -18,4 +19,50 @@ pub fn get_path(path: &str) -> String {
     };
 
     (here + "/" + path)
-}
\ No newline at end of file
+}
+
+//where files that belong to the player go, like the log
+pub fn data_dir() -> PathBuf {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|h| h.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| home().map(|h| h.join(".local").join("share")))
+    };
+
+    base.unwrap_or_else(|| PathBuf::from(get_path(""))).join("moving-tower")
+}
+
+//small xorshift generator, everything random is seeded so runs can be reproduced
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng {
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    //uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
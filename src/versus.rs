@@ -0,0 +1,290 @@
+use sfml::graphics::*;
+use sfml::system::Vector2f;
+use sfml::window::*;
+use sfml::window::joystick::{self, Axis};
+
+use crate::actors::*;
+use crate::assets::AssetManager;
+use crate::scenes::{score_rate, Scene, State};
+use crate::sim::{TowerInput, TowerState};
+use crate::theme::Theme;
+use crate::ui::{center_text, PLAYER_COLORS};
+
+use super::{WIN_HEIGHT, WIN_WIDTH};
+
+const ROUNDS_TO_WIN: u32 = 2; //best of three
+const RETICLE_SPEED: f32 = 420.0;
+const STICK_DEADZONE: f32 = 20.0;
+
+//player two steers a reticle with the keyboard or the first gamepad, the tower aims at it
+//and teleports towards it on space, enter or a gamepad button
+struct Reticle<'a> {
+    shape: CircleShape<'a>,
+    position: Vector2f,
+    teleport: bool,
+}
+
+impl<'a> Reticle<'a> {
+    fn new(position: Vector2f, color: Color) -> Reticle<'a> {
+        let shape = {
+            let mut c = CircleShape::new(8.0, 16);
+            c.set_origin(Vector2f::new(8.0, 8.0));
+            c.set_fill_color(&Color::TRANSPARENT);
+            c.set_outline_thickness(3.0);
+            c.set_outline_color(&color);
+            c.set_position(position);
+
+            c
+        };
+
+        Reticle {
+            shape,
+            position,
+            teleport: false,
+        }
+    }
+
+    fn update(&mut self, d: f32, tower: &mut Tower) {
+        let mut dir = Vector2f::new(0.0, 0.0);
+
+        if Key::W.is_pressed() || Key::Up.is_pressed() { dir.y -= 1.0; }
+        if Key::S.is_pressed() || Key::Down.is_pressed() { dir.y += 1.0; }
+        if Key::A.is_pressed() || Key::Left.is_pressed() { dir.x -= 1.0; }
+        if Key::D.is_pressed() || Key::Right.is_pressed() { dir.x += 1.0; }
+
+        if joystick::is_connected(0) {
+            let x = joystick::axis_position(0, Axis::X);
+            let y = joystick::axis_position(0, Axis::Y);
+
+            if x.abs() > STICK_DEADZONE { dir.x += x / 100.0; }
+            if y.abs() > STICK_DEADZONE { dir.y += y / 100.0; }
+        }
+
+        let l = f32::sqrt(dir.x.powi(2) + dir.y.powi(2));
+        if l > 1.0 {
+            dir.x /= l;
+            dir.y /= l;
+        }
+
+        self.position.x = (self.position.x + dir.x * RETICLE_SPEED * d).max(0.0).min(WIN_WIDTH);
+        self.position.y = (self.position.y + dir.y * RETICLE_SPEED * d).max(0.0).min(WIN_HEIGHT);
+        self.shape.set_position(self.position);
+
+        tower.input(TowerInput::Aim(self.position.x, self.position.y));
+
+        if self.teleport {
+            self.teleport = false;
+            tower.input(TowerInput::Teleport(self.position.x, self.position.y));
+        }
+    }
+
+    fn event(&mut self, evt: Event) {
+        match evt {
+            Event::KeyPressed { code: Key::Space, .. } |
+            Event::KeyPressed { code: Key::Return, .. } |
+            Event::JoystickButtonPressed { joystickid: 0, .. } => {
+                self.teleport = true;
+            }
+
+            _ => {}
+        }
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        win.draw(&self.shape);
+    }
+}
+
+#[derive(PartialEq)]
+enum Phase {
+    Playing,
+    RoundOver,
+    MatchOver,
+}
+
+pub struct VersusScene<'a> {
+    am: &'a AssetManager,
+    theme: Theme,
+    towers: Vec<Tower<'a>>,
+    reticle: Reticle<'a>,
+    wins: [u32; 2],
+    points: [f32; 2],
+    round: u32,
+    phase: Phase,
+    proceed: bool,
+    score_text: Text<'a>,
+    message_text: Text<'a>,
+}
+
+impl<'a> VersusScene<'a> {
+    pub fn new(am: &'a AssetManager, theme: &Theme) -> VersusScene<'a> {
+        let score_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.hud_size);
+            t.set_fill_color(&theme.text);
+
+            t
+        };
+
+        let message_text = {
+            let mut t = Text::new("", am.get_font(theme.font), theme.body_size);
+            t.set_fill_color(&theme.text);
+
+            t
+        };
+
+        let mut v = VersusScene {
+            am,
+            theme: *theme,
+            towers: Vec::new(),
+            reticle: Reticle::new(Vector2f::new(0.0, 0.0), PLAYER_COLORS[1]),
+            wins: [0, 0],
+            points: [0.0, 0.0],
+            round: 0,
+            phase: Phase::Playing,
+            proceed: false,
+            score_text,
+            message_text,
+        };
+
+        v.new_round();
+
+        v
+    }
+
+    fn new_round(&mut self) {
+        let starts = [
+            (Vector2f::new(WIN_WIDTH / 4.0, WIN_HEIGHT / 2.0), 0.0),
+            (Vector2f::new(WIN_WIDTH * 3.0 / 4.0, WIN_HEIGHT / 2.0), 180.0),
+        ];
+
+        self.towers = starts.iter().zip(PLAYER_COLORS.iter()).map(|((p, r), c)| {
+            let mut t = Tower::with_position(self.am, &self.theme, *p, *r);
+            t.set_tint(*c);
+
+            t
+        }).collect();
+
+        self.reticle = Reticle::new(Vector2f::new(WIN_WIDTH / 2.0, WIN_HEIGHT / 2.0), PLAYER_COLORS[1]);
+        self.round += 1;
+        self.phase = Phase::Playing;
+        self.update_score_text();
+    }
+
+    fn end_round(&mut self) {
+        let message = match (self.towers[0].is_dead(), self.towers[1].is_dead()) {
+            (true, true) => "Both got hit, the round is a draw".to_string(),
+            (false, true) => {
+                self.wins[0] += 1;
+                "Player 1 wins the round".to_string()
+            }
+            _ => {
+                self.wins[1] += 1;
+                "Player 2 wins the round".to_string()
+            }
+        };
+
+        let message = if let Some(w) = self.wins.iter().position(|w| *w >= ROUNDS_TO_WIN) {
+            self.phase = Phase::MatchOver;
+            format!("{}\nPLAYER {} WINS THE MATCH {} : {}\n\nClick or press space to return to the menu",
+                    message, w + 1, self.wins[w], self.wins[1 - w])
+        } else {
+            self.phase = Phase::RoundOver;
+            format!("{}\n\nClick or press space for the next round", message)
+        };
+
+        self.message_text.set_string(message.as_str());
+        center_text(&mut self.message_text, WIN_HEIGHT / 2.0 - 60.0);
+        self.update_score_text();
+    }
+
+    fn update_score_text(&mut self) {
+        let s = format!("P1 {:.0}    [{} : {}]  Round {}    {:.0} P2",
+                        self.points[0], self.wins[0], self.wins[1], self.round, self.points[1]);
+
+        self.score_text.set_string(s.as_str());
+        center_text(&mut self.score_text, 5.0);
+    }
+}
+
+impl<'a> Scene for VersusScene<'a> {
+    fn update(&mut self, d: f32) -> Option<State> {
+        if self.phase != Phase::Playing {
+            if self.proceed {
+                self.proceed = false;
+
+                if self.phase == Phase::MatchOver {
+                    return Some(State::Menu(None));
+                }
+
+                self.new_round();
+            }
+
+            return None;
+        }
+
+        self.reticle.update(d, &mut self.towers[1]);
+
+        for t in &mut self.towers {
+            t.update(d);
+        }
+
+        {
+            let (a, b) = self.towers.split_at_mut(1);
+            a[0].check_hits(&b[0]);
+            b[0].check_hits(&a[0]);
+        }
+
+        let rate = score_rate(self.towers.iter().map(|t| t.num_bullets()).sum());
+        for (p, t) in self.points.iter_mut().zip(self.towers.iter()) {
+            if !t.is_dead() {
+                *p += rate * d;
+            }
+        }
+
+        if self.towers.iter().any(|t| t.is_dead()) {
+            self.end_round();
+        } else {
+            self.update_score_text();
+        }
+
+        None
+    }
+
+    fn draw(&self, win: &mut RenderWindow) {
+        for t in &self.towers {
+            t.draw(win);
+        }
+
+        win.draw(&self.score_text);
+
+        if self.phase == Phase::Playing {
+            self.reticle.draw(win);
+        } else {
+            win.draw(&self.message_text);
+        }
+    }
+
+    fn events(&mut self, evt: Event) {
+        if self.phase != Phase::Playing {
+            match evt {
+                Event::MouseButtonPressed { .. } |
+                Event::KeyPressed { code: Key::Space, .. } |
+                Event::KeyPressed { code: Key::Return, .. } |
+                Event::JoystickButtonPressed { .. } => {
+                    self.proceed = true;
+                }
+
+                _ => {}
+            }
+
+            return;
+        }
+
+        self.towers[0].events(evt);
+        self.reticle.event(evt);
+    }
+
+    fn towers(&self) -> Vec<&TowerState> {
+        self.towers.iter().map(|t| t.state()).collect()
+    }
+}